@@ -1,23 +1,269 @@
-use super::{ErrorKind, Result};
-use std::fs::{create_dir_all, File, OpenOptions};
-use std::path::PathBuf;
-use std::ops::Sub;
-use serde_json;
+use super::{Error, ErrorKind, Result};
+use settings::Settings;
+use slack::interaction::Payload;
+use slack::signing::Signature;
+use slack::slash_command::Request;
+use store::{self, Store};
 use chrono::Date as cDate;
-use chrono::{Datelike, Local, Timelike};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use ring::constant_time;
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct DayCommit {
+    /// The day the shift started, which it is filed under
     pub date: Date,
     pub start_time: Time,
     pub end_time: Option<Time>,
+    /// `None` in records from before overnight shifts, they ended on `date`.
+    #[serde(default)]
+    pub end_date: Option<Date>,
     pub message: Option<String>,
     pub participants: Vec<Participant>,
+    /// The zone all times of the shift are in. `None` in records from before
+    /// time zones were configurable, which `App` reads in the zone configured
+    /// for their channel.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Breaks of the whole shift
+    #[serde(default)]
+    pub breaks: Vec<Break>,
+    /// When the shift was stashed, while it is. `None` in shifts stashed
+    /// before this was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stashed_at: Option<(Date, Time)>,
+    /// The branch a stashed shift was on, `None` for the default branch and
+    /// in shifts stashed before this was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stashed_branch: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+impl DayCommit {
+    pub fn tz(&self) -> Option<Tz> {
+        self.timezone.as_ref().and_then(|name| name.parse().ok())
+    }
+
+    /// Time from `start` to `end` of the shift, counting in its zone's
+    /// offsets when it is known.
+    pub fn elapsed(&self, start: &NaiveDateTime, end: &NaiveDateTime) -> Result<TimeDiff> {
+        let diff = TimeDiff::between(start, end)?;
+        let tz = match self.tz() {
+            Some(tz) => tz,
+            None => return Ok(diff),
+        };
+        match (
+            tz.from_local_datetime(start).earliest(),
+            tz.from_local_datetime(end).earliest(),
+        ) {
+            (Some(start), Some(end)) => TimeDiff::between(&start.naive_utc(), &end.naive_utc()),
+            _ => Ok(diff),
+        }
+    }
+
+    pub fn start(&self) -> NaiveDateTime {
+        self.date.at(&self.start_time)
+    }
+
+    pub fn end(&self) -> Option<NaiveDateTime> {
+        self.end_time
+            .as_ref()
+            .map(|t| self.end_date.as_ref().unwrap_or(&self.date).at(t))
+    }
+
+    pub fn set_end(&mut self, end: NaiveDateTime) {
+        self.end_date = Some(end.date().into());
+        self.end_time = Some(end.time().into());
+    }
+
+    /// The first moment at `time` from the start of the shift on, so a time
+    /// earlier than the start time is taken as the next day.
+    pub fn at(&self, time: &Time) -> NaiveDateTime {
+        let at = self.date.at(time);
+        if at < self.start() {
+            at + ::chrono::Duration::days(1)
+        } else {
+            at
+        }
+    }
+
+    /// Like `at`, but fails if that moment hasn't come yet at `now`.
+    pub fn at_before(&self, time: &Time, now: &NaiveDateTime) -> Result<NaiveDateTime> {
+        let at = self.at(time);
+        if at <= *now {
+            Ok(at)
+        } else if at.date() != self.start().date() {
+            // more likely meant before the start than tomorrow
+            bail!(ErrorKind::NegativeDuration(
+                self.start_time.to_short_str(),
+                time.to_short_str()
+            ))
+        } else {
+            bail!(ErrorKind::FutureTime(time.to_short_str()))
+        }
+    }
+
+    /// Ends the shift at `end_time` on its day, or on the next day if it is
+    /// marked so. An end before the start is left for `validate` to refuse.
+    pub fn set_end_time(&mut self, end_time: &EndTime) {
+        let mut end = self.date.at(&end_time.time);
+        if end_time.next_day {
+            end += ::chrono::Duration::days(1);
+        }
+        self.set_end(end);
+    }
+
+    /// Breaks of the whole shift and of `p`, merged and with the running ones
+    /// lasting until `until`.
+    pub fn break_ranges(
+        &self,
+        p: Option<&Participant>,
+        until: &NaiveDateTime,
+    ) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let mut ranges: Vec<(NaiveDateTime, NaiveDateTime)> = self.breaks
+            .iter()
+            .chain(p.into_iter().flat_map(|p| p.breaks.iter()))
+            .map(|b| (b.start(), b.end().unwrap_or(*until)))
+            .filter(|&(start, end)| start < end)
+            .collect();
+        ranges.sort();
+
+        let mut merged: Vec<(NaiveDateTime, NaiveDateTime)> = vec![];
+        for (start, end) in ranges {
+            if let Some(last) = merged.last_mut() {
+                if start <= last.1 {
+                    last.1 = ::std::cmp::max(last.1, end);
+                    continue;
+                }
+            }
+            merged.push((start, end));
+        }
+        merged
+    }
+
+    /// Ends the running breaks of the shift and of every participant at `at`.
+    pub fn end_breaks(&mut self, at: NaiveDateTime) -> Result<()> {
+        Break::end_in(&mut self.breaks, at)?;
+        for p in &mut self.participants {
+            p.resume(at)?;
+        }
+        Ok(())
+    }
+
+    /// Time from `start` to `end` that isn't in any of `breaks`.
+    pub fn worked_between(
+        &self,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+        breaks: &[(NaiveDateTime, NaiveDateTime)],
+    ) -> Result<TimeDiff> {
+        let mut worked = self.elapsed(start, end)?;
+        for &(break_start, break_end) in breaks {
+            let break_start = ::std::cmp::max(break_start, *start);
+            let break_end = ::std::cmp::min(break_end, *end);
+            if break_start < break_end {
+                worked = worked.checked_sub(self.elapsed(&break_start, &break_end)?)?;
+            }
+        }
+        Ok(worked)
+    }
+
+    /// Time worked without breaks, `None` while the shift is still running.
+    pub fn duration(&self) -> Result<Option<TimeDiff>> {
+        match self.end() {
+            Some(ref end) => {
+                let breaks = self.break_ranges(None, end);
+                self.worked_between(&self.start(), end, &breaks).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Fails if the shift, a stay of a participant or a break ends before it
+    /// starts, or a stay or a break is outside of the shift.
+    pub fn validate(&self) -> Result<()> {
+        self.duration()?;
+        let start = self.start();
+        let end = self.end();
+        let breaks = self.breaks
+            .iter()
+            .chain(self.participants.iter().flat_map(|p| p.breaks.iter()));
+        for b in breaks {
+            self.elapsed(&start, &b.start())?;
+            if let Some(ref end) = end {
+                self.elapsed(&b.start(), &b.end().unwrap_or(*end))?;
+                self.elapsed(&b.end().unwrap_or(*end), end)?;
+            }
+        }
+        for p in &self.participants {
+            for i in p.stays() {
+                self.elapsed(&start, &i.in_at(&self.date))?;
+            }
+        }
+        if let Some(ref end) = end {
+            for p in &self.participants {
+                p.worked(self, end)?;
+                for i in p.stays() {
+                    if let Some(ref out) = i.out_at(&self.date) {
+                        self.elapsed(out, end)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `"18:0 ~ 2:0"`, with the date of an end on another day.
+    pub fn hours_str(&self) -> String {
+        match self.end() {
+            Some(ref end) => format!(
+                "{} ~ {}",
+                self.start_time.to_short_str(),
+                self.date.short_str(end)
+            ),
+            None => format!("{} ~", self.start_time.to_short_str()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Date(pub i32, pub u32, pub u32);
 
+impl Date {
+    pub fn at(&self, time: &Time) -> NaiveDateTime {
+        NaiveDate::from_ymd(self.0, self.1, self.2).and_hms(time.0, time.1, 0)
+    }
+
+    /// `"9:30"` for a moment on this day, `"10/17 2:0"` for other days.
+    pub fn short_str(&self, at: &NaiveDateTime) -> String {
+        let time: Time = at.time().into();
+        if Date::from(at.date()) == *self {
+            time.to_short_str()
+        } else {
+            format!("{}/{} {}", at.month(), at.day(), time.to_short_str())
+        }
+    }
+}
+
+impl ::std::str::FromStr for Date {
+    type Err = ();
+    /// `"2018-03-02"`
+    fn from_str(s: &str) -> ::std::result::Result<Date, ()> {
+        let mut split = s.splitn(3, '-');
+        let year: i32 = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        let month: u32 = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        let day: u32 = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        NaiveDate::from_ymd_opt(year, month, day)
+            .map(Date::from)
+            .ok_or(())
+    }
+}
+
+impl From<NaiveDate> for Date {
+    fn from(d: NaiveDate) -> Date {
+        Date(d.year(), d.month(), d.day())
+    }
+}
+
 impl From<Date> for cDate<Local> {
     fn from(d: Date) -> cDate<Local> {
         use chrono::prelude::*;
@@ -37,10 +283,12 @@ impl ::std::fmt::Display for Date {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Time(pub u32, pub u32);
 
-pub struct TimeDiff(pub i32, pub i32);
+/// A length of time in whole minutes.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct TimeDiff(pub u32);
 
 impl From<Time> for ::chrono::NaiveTime {
     fn from(t: Time) -> ::chrono::NaiveTime {
@@ -54,84 +302,409 @@ impl From<::chrono::NaiveTime> for Time {
     }
 }
 
-impl From<Time> for f32 {
-    fn from(t: Time) -> f32 {
-        (t.0 as f32) + ((t.1 as f32) / 60f32)
+impl ::std::fmt::Display for Time {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}시 {}분", self.0, self.1)
+    }
+}
+
+impl ::std::fmt::Display for TimeDiff {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}시간 {}분", self.0 / 60, self.0 % 60)
     }
 }
 
-impl From<f32> for Time {
-    fn from(f: f32) -> Time {
-        Time(f as u32, ((f * 60f32) as u32) % 60)
+impl Time {
+    pub fn to_short_str(&self) -> String {
+        format!("{}:{}", self.0, self.1)
     }
 }
 
-impl<'a> From<&'a Time> for f32 {
-    fn from(t: &'a Time) -> f32 {
-        (t.0 as f32) + ((t.1 as f32) / 60f32)
+impl ::std::str::FromStr for Time {
+    type Err = ();
+    /// `"9:30"` or `"09:30"`
+    fn from_str(s: &str) -> ::std::result::Result<Time, ()> {
+        let mut split = s.splitn(2, ':');
+        let hour: u32 = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        let minute: u32 = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        if hour >= 24 || minute >= 60 {
+            return Err(());
+        }
+        Ok(Time(hour, minute))
     }
 }
 
-impl From<TimeDiff> for f32 {
-    fn from(t: TimeDiff) -> f32 {
-        (t.0 as f32) + ((t.1 as f32) / 60f32)
+/// An end time given by hand, `"6:00+1"` for the day after the shift started.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EndTime {
+    pub time: Time,
+    pub next_day: bool,
+}
+
+impl ::std::str::FromStr for EndTime {
+    type Err = ();
+    /// `"18:00"` or `"6:00+1"`
+    fn from_str(s: &str) -> ::std::result::Result<EndTime, ()> {
+        let (time, next_day) = if s.ends_with("+1") {
+            (&s[..s.len() - 2], true)
+        } else {
+            (s, false)
+        };
+        Ok(EndTime {
+            time: time.parse()?,
+            next_day,
+        })
     }
 }
 
-impl From<f32> for TimeDiff {
-    fn from(f: f32) -> TimeDiff {
-        TimeDiff(f as i32, ((f * 60f32) as i32) % 60)
+impl TimeDiff {
+    pub fn to_short_str(&self) -> String {
+        format!("{}:{}", self.0 / 60, self.0 % 60)
     }
+
+    /// Fails with `NegativeDuration` if `end` is before `start`.
+    pub fn between(start: &NaiveDateTime, end: &NaiveDateTime) -> Result<TimeDiff> {
+        let minutes = end.signed_duration_since(*start).num_minutes();
+        if minutes < 0 {
+            let short_str = |at: &NaiveDateTime| {
+                format!("{}/{} {}:{}", at.month(), at.day(), at.hour(), at.minute())
+            };
+            bail!(ErrorKind::NegativeDuration(short_str(start), short_str(end)));
+        }
+        if minutes > i64::from(u32::max_value()) {
+            bail!(ErrorKind::DurationOverflow);
+        }
+        Ok(TimeDiff(minutes as u32))
+    }
+
+    pub fn checked_add(self, rhs: TimeDiff) -> Result<TimeDiff> {
+        match self.0.checked_add(rhs.0) {
+            Some(m) => Ok(TimeDiff(m)),
+            None => bail!(ErrorKind::DurationOverflow),
+        }
+    }
+
+    pub fn checked_sub(self, rhs: TimeDiff) -> Result<TimeDiff> {
+        match self.0.checked_sub(rhs.0) {
+            Some(m) => Ok(TimeDiff(m)),
+            None => bail!(ErrorKind::DurationOverflow),
+        }
+    }
+
+    pub fn sum<I: IntoIterator<Item = TimeDiff>>(diffs: I) -> Result<TimeDiff> {
+        diffs
+            .into_iter()
+            .try_fold(TimeDiff(0), |sum, d| sum.checked_add(d))
+    }
+}
+
+/// The days of one archived `(year, month)`.
+pub type MonthCommits = ((i32, u32), Vec<DayCommit>);
+
+/// Identifies a committed day of the current month: the `n`th shift of `day`,
+/// written `3` for the first and `3_1`, `3_2`, ... for the later ones.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct DayKey {
+    pub day: u32,
+    pub n: usize,
 }
 
-impl Sub for Time {
-    type Output = TimeDiff;
-    fn sub(self, rhs: Self) -> Self::Output {
-        let self_f: f32 = self.into();
-        let rhs_f: f32 = rhs.into();
-        let diff: f32 = self_f - rhs_f;
-        diff.into()
+impl ::std::fmt::Display for DayKey {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        if self.n == 0 {
+            write!(f, "{}", self.day)
+        } else {
+            write!(f, "{}_{}", self.day, self.n)
+        }
     }
 }
 
-impl<'a> Sub for &'a Time {
-    type Output = TimeDiff;
-    fn sub(self, rhs: Self) -> Self::Output {
-        let self_f: f32 = self.into();
-        let rhs_f: f32 = rhs.into();
-        let diff: f32 = self_f - rhs_f;
-        diff.into()
+impl ::std::str::FromStr for DayKey {
+    type Err = ();
+    fn from_str(s: &str) -> ::std::result::Result<DayKey, ()> {
+        let mut split = s.splitn(2, '_');
+        let day = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        let n = match split.next() {
+            Some(n) => n.parse().map_err(|_| ())?,
+            None => 0,
+        };
+        Ok(DayKey { day, n })
     }
 }
 
-impl ::std::fmt::Display for Time {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        write!(f, "{}시 {}분", self.0, self.1)
+/// Numbers the shifts of each day in order, the way `DayKey` does.
+pub fn key_days(days: Vec<DayCommit>) -> Vec<(DayKey, DayCommit)> {
+    let mut keyed: Vec<(DayKey, DayCommit)> = vec![];
+    for day_commit in days {
+        let n = keyed
+            .iter()
+            .filter(|(key, _)| key.day == day_commit.date.2)
+            .count();
+        keyed.push((
+            DayKey {
+                day: day_commit.date.2,
+                n,
+            },
+            day_commit,
+        ));
     }
+    keyed
 }
 
-impl ::std::fmt::Display for TimeDiff {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        write!(f, "{}시간 {}분", self.0, self.1)
+/// Groups days by `(year, month)` of their own date, keeping their order.
+pub fn group_by_month(days: Vec<DayCommit>) -> Vec<MonthCommits> {
+    use std::collections::BTreeMap;
+    let mut months: BTreeMap<(i32, u32), Vec<DayCommit>> = BTreeMap::new();
+    for day_commit in days {
+        months
+            .entry((day_commit.date.0, day_commit.date.1))
+            .or_default()
+            .push(day_commit);
     }
+    months.into_iter().collect()
 }
 
-impl Time {
-    pub fn to_short_str(&self) -> String {
-        format!("{}:{}", self.0, self.1)
+/// Every Slack channel has its own shifts and records.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Session {
+    pub team_id: String,
+    pub channel_id: String,
+}
+
+impl<'a> From<&'a Request> for Session {
+    fn from(r: &'a Request) -> Session {
+        Session {
+            team_id: r.team_id.clone(),
+            channel_id: r.channel_id.clone(),
+        }
     }
 }
 
-impl TimeDiff {
-    pub fn to_short_str(&self) -> String {
-        format!("{}:{}", self.0, self.1)
+impl<'a> From<&'a Payload> for Session {
+    fn from(p: &'a Payload) -> Session {
+        Session {
+            team_id: p.team.id.clone(),
+            channel_id: p.channel.id.clone(),
+        }
     }
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Participant {
+    /// When they first came in
     pub commit_time: Time,
     pub name: String,
+    /// Empty in records written before check-outs existed, see `stays`.
+    #[serde(default)]
+    pub intervals: Vec<Interval>,
+    /// Their own breaks, on top of the ones of the whole shift
+    #[serde(default)]
+    pub breaks: Vec<Break>,
+}
+
+/// A break of the whole shift or of a participant, `end_time` is `None`
+/// while it lasts.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Break {
+    pub start_date: Date,
+    pub start_time: Time,
+    pub end_date: Option<Date>,
+    pub end_time: Option<Time>,
+    pub reason: Option<String>,
+}
+
+impl Break {
+    pub fn start(&self) -> NaiveDateTime {
+        self.start_date.at(&self.start_time)
+    }
+
+    pub fn end(&self) -> Option<NaiveDateTime> {
+        match (&self.end_date, &self.end_time) {
+            (Some(date), Some(time)) => Some(date.at(time)),
+            _ => None,
+        }
+    }
+
+    /// Starts a break in `breaks` at `at`. `Ok(false)` if one is running.
+    pub fn start_in(
+        breaks: &mut Vec<Break>,
+        at: NaiveDateTime,
+        reason: Option<String>,
+    ) -> Result<bool> {
+        if let Some(last) = breaks.last() {
+            match last.end() {
+                Some(ref end) => {
+                    TimeDiff::between(end, &at)?;
+                }
+                None => return Ok(false),
+            }
+        }
+        breaks.push(Break {
+            start_date: at.date().into(),
+            start_time: at.time().into(),
+            end_date: None,
+            end_time: None,
+            reason,
+        });
+        Ok(true)
+    }
+
+    /// Ends the running break in `breaks` at `at`. `Ok(false)` if none is.
+    pub fn end_in(breaks: &mut [Break], at: NaiveDateTime) -> Result<bool> {
+        match breaks.last_mut() {
+            Some(ref mut last) if last.end_time.is_none() => {
+                TimeDiff::between(&last.start(), &at)?;
+                last.end_date = Some(at.date().into());
+                last.end_time = Some(at.time().into());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// `"12:0 ~ 13:0 점심"` in a shift started on `date`.
+    pub fn to_str(&self, date: &Date) -> String {
+        let mut s = format!("{} ~", date.short_str(&self.start()));
+        if let Some(ref end) = self.end() {
+            s = s + " " + &date.short_str(end);
+        }
+        if let Some(ref reason) = self.reason {
+            s = s + " " + reason;
+        }
+        s
+    }
+}
+
+/// A stay of a participant, `out_time` is `None` while they are still in.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Interval {
+    pub in_time: Time,
+    pub out_time: Option<Time>,
+    /// `None` in records from before overnight shifts, the day the shift
+    /// started.
+    #[serde(default)]
+    pub in_date: Option<Date>,
+    #[serde(default)]
+    pub out_date: Option<Date>,
+}
+
+impl Interval {
+    fn new(at: NaiveDateTime) -> Interval {
+        Interval {
+            in_time: at.time().into(),
+            out_time: None,
+            in_date: Some(at.date().into()),
+            out_date: None,
+        }
+    }
+
+    /// `date` is the day the shift started.
+    pub fn in_at(&self, date: &Date) -> NaiveDateTime {
+        self.in_date.as_ref().unwrap_or(date).at(&self.in_time)
+    }
+
+    pub fn out_at(&self, date: &Date) -> Option<NaiveDateTime> {
+        self.out_time
+            .as_ref()
+            .map(|t| self.out_date.as_ref().unwrap_or(date).at(t))
+    }
+}
+
+impl Participant {
+    pub fn new(name: String, at: NaiveDateTime) -> Participant {
+        Participant {
+            commit_time: at.time().into(),
+            name,
+            intervals: vec![Interval::new(at)],
+            breaks: vec![],
+        }
+    }
+
+    /// Old records have no intervals, they stayed from `commit_time` on.
+    pub fn stays(&self) -> Vec<Interval> {
+        if self.intervals.is_empty() {
+            vec![
+                Interval {
+                    in_time: self.commit_time.clone(),
+                    out_time: None,
+                    in_date: None,
+                    out_date: None,
+                },
+            ]
+        } else {
+            self.intervals.clone()
+        }
+    }
+
+    pub fn is_in(&self) -> bool {
+        self.stays().last().map_or(false, |i| i.out_time.is_none())
+    }
+
+    /// Returns `false` if they are already out.
+    pub fn check_out(&mut self, at: NaiveDateTime) -> bool {
+        if !self.is_in() {
+            return false;
+        }
+        self.intervals = self.stays();
+        if let Some(last) = self.intervals.last_mut() {
+            last.out_time = Some(at.time().into());
+            last.out_date = Some(at.date().into());
+        }
+        true
+    }
+
+    /// Returns `false` if they are already in.
+    pub fn check_in(&mut self, at: NaiveDateTime) -> bool {
+        if self.is_in() {
+            return false;
+        }
+        self.intervals = self.stays();
+        self.intervals.push(Interval::new(at));
+        true
+    }
+
+    /// `Ok(false)` if they are out or already on a break.
+    pub fn pause(&mut self, at: NaiveDateTime, reason: Option<String>) -> Result<bool> {
+        if !self.is_in() {
+            return Ok(false);
+        }
+        Break::start_in(&mut self.breaks, at, reason)
+    }
+
+    /// `Ok(false)` if they are not on a break.
+    pub fn resume(&mut self, at: NaiveDateTime) -> Result<bool> {
+        Break::end_in(&mut self.breaks, at)
+    }
+
+    /// Length of all stays in `day_commit` without breaks, counting the ones
+    /// still open until `until`.
+    pub fn worked(&self, day_commit: &DayCommit, until: &NaiveDateTime) -> Result<TimeDiff> {
+        let date = &day_commit.date;
+        let breaks = day_commit.break_ranges(Some(self), until);
+        let mut worked = TimeDiff(0);
+        for i in self.stays() {
+            let out = i.out_at(date).unwrap_or(*until);
+            let stay = day_commit.worked_between(&i.in_at(date), &out, &breaks)?;
+            worked = worked.checked_add(stay)?;
+        }
+        Ok(worked)
+    }
+
+    /// `"9:30 ~ 12:0, 13:0 ~"` in a shift started on `date`.
+    pub fn stays_str(&self, date: &Date) -> String {
+        let stays: Vec<String> = self.stays()
+            .iter()
+            .map(|i| match i.out_at(date) {
+                Some(ref out) => format!(
+                    "{} ~ {}",
+                    date.short_str(&i.in_at(date)),
+                    date.short_str(out)
+                ),
+                None => format!("{} ~", date.short_str(&i.in_at(date))),
+            })
+            .collect();
+        stays.join(", ")
+    }
 }
 
 impl PartialEq<Participant> for Participant {
@@ -140,193 +713,565 @@ impl PartialEq<Participant> for Participant {
     }
 }
 
-#[derive(Deserialize)]
+pub struct PushPreview {
+    pub months: Vec<MonthCommits>,
+    pub working_open: bool,
+}
+
 pub struct App {
-    pub verification_token: String,
-    pub api_token: String,
-    pub data_path: String,
+    pub settings: Settings,
+    store: Box<Store + Send + Sync>,
 }
 
 impl App {
     pub fn try_new() -> Result<App> {
-        use std::env::args;
-        use std::path::Path;
-        use config::{Config, File};
-        let mut app = Config::new();
-        app.merge(if args().len() >= 2 {
-            File::from(Path::new(&args().nth(1).unwrap()))
-        } else {
-            File::with_name("settings")
-        })?;
-        app.try_into::<App>()
-            .map_err(|e| ErrorKind::Config(e).into())
+        let settings = Settings::try_new()?;
+        settings.check_timezones()?;
+        let store = store::open(&settings)?;
+        Ok(App::with_store(settings, store))
     }
 
     pub fn assure_new() -> App {
-        use std::fs::read_dir;
-        let mut app = match App::try_new() {
+        let settings = Settings::assure_new();
+        if let Err(e) = settings.check_timezones() {
+            panic!("Settings file parse error!, {}", e);
+        }
+        let store = match store::open(&settings) {
             Ok(s) => s,
-            Err(e) => panic!("Settings file parse error!, {}", e),
+            Err(e) => panic!("Storage open error!, {}", e),
         };
-        if let Err(e) = read_dir(&app.data_path) {
-            panic!("Invalid data folder. Check settings file!, {}", e);
+        App::with_store(settings, store)
+    }
+
+    pub fn with_store(settings: Settings, store: Box<Store + Send + Sync>) -> App {
+        App { settings, store }
+    }
+
+    /// Checks that a request comes from Slack, by its signature over the raw
+    /// `body`, or by the legacy `token` if signatures aren't available and
+    /// `accept_verification_token` allows it.
+    pub fn verify(&self, signature: Option<&Signature>, body: &str, token: &str) -> Result<()> {
+        if let (Some(secret), Some(signature)) = (&self.settings.signing_secret, signature) {
+            return signature.verify(secret, body, Utc::now().timestamp());
         }
-        if !app.data_path.ends_with('/') {
-            app.data_path.push('/');
+        match self.settings.verification_token {
+            Some(ref expected) if self.settings.accept_verification_token => {
+                constant_time::verify_slices_are_equal(expected.as_bytes(), token.as_bytes())
+                    .map_err(|_| ErrorKind::InvalidToken.into())
+            }
+            _ => bail!(ErrorKind::InvalidSignature),
         }
-        app
     }
 
-    pub fn verify(&self, token: &str) -> bool {
-        token == self.verification_token
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        self.settings.admins.iter().any(|a| a == user_id)
     }
 
-    fn get_commit_from_file(file: &File) -> Result<DayCommit> {
-        serde_json::from_reader(file).map_err(|e| ErrorKind::Json(e).into())
+    /// The participant a Slack user is recorded as when they click a button.
+    pub fn participant_name(&self, user_id: &str, username: &str) -> String {
+        match self.settings.user_name(user_id) {
+            Some(name) => name.to_owned(),
+            None if username.is_empty() => user_id.to_owned(),
+            None => username.to_owned(),
+        }
     }
 
-    fn get_commit_from_path(path: PathBuf) -> Result<DayCommit> {
-        let file = File::open(path)?;
-        App::get_commit_from_file(&file)
+    /// Wall-clock time in the time zone of `session`.
+    pub fn now(&self, session: &Session) -> Result<NaiveDateTime> {
+        let tz = self.settings.timezone(session)?;
+        Ok(Utc::now().with_timezone(&tz).naive_local())
     }
 
-    pub fn create_working_file(&self, date: Date, time: Time) -> Result<DayCommit> {
-        let mut path = PathBuf::from(&self.data_path);
-        path.push("working.json");
-
-        if path.exists() {
-            bail!(ErrorKind::AlreadyInitialized);
+    /// Starts a shift on `branch`, `None` being the default branch. Fails
+    /// with `FutureTime` if the shift would start later than now.
+    pub fn create_working_file(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        date: Date,
+        time: Time,
+    ) -> Result<DayCommit> {
+        if date.at(&time) > self.now(session)? {
+            bail!(ErrorKind::FutureTime(time.to_short_str()));
         }
 
-        let file = File::create(path)?;
-
         let day_commit = DayCommit {
             date,
             start_time: time,
             end_time: None,
+            end_date: None,
             message: None,
             participants: vec![],
+            timezone: Some(self.settings.timezone_name(session).to_owned()),
+            breaks: vec![],
+            stashed_at: None,
+            stashed_branch: None,
         };
 
-        serde_json::to_writer_pretty(file, &day_commit)?;
+        let _lock = self.store.lock(session)?;
+        self.store.create_working(session, branch, &day_commit)?;
 
         Ok(day_commit)
     }
 
-    pub fn get_working_file(&self, option: &mut OpenOptions) -> Result<::std::fs::File> {
-        let mut path = PathBuf::from(&self.data_path);
-        path.push("working.json");
+    pub fn edit_working_commit<F>(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        f: F,
+    ) -> Result<DayCommit>
+    where
+        F: FnOnce(DayCommit) -> Result<DayCommit>,
+    {
+        let _lock = self.store.lock(session)?;
+        let mut day_commit = self.zoned(session, self.store.get_working(session, branch)?);
 
-        if !path.exists() {
-            bail!(ErrorKind::NotInitialized);
+        day_commit = f(day_commit)?;
+
+        self.store.put_working(session, branch, &day_commit)?;
+
+        Ok(day_commit)
+    }
+
+    pub fn get_working_commit(&self, session: &Session, branch: Option<&str>) -> Result<DayCommit> {
+        Ok(self.zoned(session, self.store.get_working(session, branch)?))
+    }
+
+    /// Every running shift with its branch, the default branch first.
+    pub fn get_working_commits(
+        &self,
+        session: &Session,
+    ) -> Result<Vec<(Option<String>, DayCommit)>> {
+        Ok(self.store
+            .get_branches(session)?
+            .into_iter()
+            .map(|(branch, day_commit)| (branch, self.zoned(session, day_commit)))
+            .collect())
+    }
+
+    pub fn remove_working_commit(&self, session: &Session, branch: Option<&str>) -> Result<()> {
+        let _lock = self.store.lock(session)?;
+        self.store.remove_working(session, branch)
+    }
+
+    /// Sets the shift on `branch` aside. Until it is popped, the whole shift
+    /// is on a break.
+    pub fn stash_working_commit(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+    ) -> Result<DayCommit> {
+        let now = self.now(session)?;
+        let _lock = self.store.lock(session)?;
+        let mut day_commit = self.zoned(session, self.store.get_working(session, branch)?);
+
+        Break::start_in(&mut day_commit.breaks, now, Some("보관".to_owned()))?;
+        day_commit.stashed_at = Some((now.date().into(), now.time().into()));
+        day_commit.stashed_branch = branch.map(|b| b.to_owned());
+
+        self.store.stash_working(session, branch, &day_commit)?;
+
+        Ok(day_commit)
+    }
+
+    /// Stashed shifts, the latest first.
+    pub fn get_stashed_commits(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        Ok(self.zoned_all(session, self.store.get_stashes(session)?))
+    }
+
+    /// Brings the `n`th stashed shift, counting from the latest, back onto
+    /// `branch` and ends its break. Fails with `AlreadyInitialized` if a shift
+    /// is running there.
+    pub fn pop_stashed_commit(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        n: usize,
+    ) -> Result<DayCommit> {
+        let now = self.now(session)?;
+        let _lock = self.store.lock(session)?;
+        match self.store.get_working(session, branch) {
+            Ok(_) => bail!(ErrorKind::AlreadyInitialized),
+            Err(Error(ErrorKind::NotInitialized, _)) => {}
+            Err(e) => return Err(e),
         }
+        let mut day_commit = match self.store.get_stashes(session)?.into_iter().nth(n) {
+            Some(day_commit) => self.zoned(session, day_commit),
+            None => bail!(ErrorKind::NoSuchStash(n)),
+        };
+
+        Break::end_in(&mut day_commit.breaks, now)?;
+        day_commit.stashed_at = None;
+        day_commit.stashed_branch = None;
+
+        self.store.pop_stash(session, n, branch, &day_commit)?;
+
+        Ok(day_commit)
+    }
+
+    /// Ends the working shift on `branch` at `end_time`, or now if it is
+    /// `None`. It joins the committed days like any other shift.
+    pub fn commit_a_day(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        end_time: Option<Time>,
+        message: String,
+    ) -> Result<DayCommit> {
+        let now = self.now(session)?;
+        let _lock = self.store.lock(session)?;
+        let mut day_commit = self.zoned(session, self.store.get_working(session, branch)?);
+
+        let end = match end_time {
+            Some(end_time) => day_commit.at_before(&end_time, &now)?,
+            None => now,
+        };
+        day_commit.set_end(end);
+        day_commit.end_breaks(end)?;
+        day_commit.message = Some(message);
+        day_commit.validate()?;
+
+        self.store.commit_day(session, &day_commit)?;
 
-        option.open(&path).map_err(|e| ErrorKind::Io(e).into())
+        self.store.remove_working(session, branch)?;
+
+        Ok(day_commit)
     }
 
-    pub fn edit_working_commit<F>(&self, f: F) -> Result<DayCommit>
+    /// Rewrites the latest committed day with `f`, the one that started last
+    /// even if the current month spans two. Fails with `NotInitialized` if
+    /// nothing was committed this month.
+    pub fn amend_last_commit<F>(&self, session: &Session, f: F) -> Result<DayCommit>
     where
         F: FnOnce(DayCommit) -> DayCommit,
     {
-        let mut day_commit: DayCommit = self.get_working_commit()?;
+        let _lock = self.store.lock(session)?;
+        let last = self.store
+            .get_keyed_days(session)?
+            .into_iter()
+            .max_by_key(|(key, day_commit)| (day_commit.start(), key.n));
+        let (key, day_commit) = match last {
+            Some(last) => last,
+            None => bail!(ErrorKind::NotInitialized),
+        };
 
-        day_commit = f(day_commit);
+        let day_commit = f(self.zoned(session, day_commit));
+        day_commit.validate()?;
 
-        let file: File = self.get_working_file(OpenOptions::new().write(true).truncate(true))?;
-        serde_json::to_writer_pretty(file, &day_commit)?;
+        self.store.put_day(session, &key, &day_commit)?;
 
         Ok(day_commit)
     }
 
-    pub fn get_working_commit(&self) -> Result<DayCommit> {
-        let file = self.get_working_file(OpenOptions::new().read(true))?;
-        App::get_commit_from_file(&file)
+    /// Rewrites any committed day of the current month with `f`, and returns
+    /// it from before and after the change.
+    pub fn edit_commit<F>(
+        &self,
+        session: &Session,
+        key: &DayKey,
+        f: F,
+    ) -> Result<(DayCommit, DayCommit)>
+    where
+        F: FnOnce(DayCommit) -> DayCommit,
+    {
+        let _lock = self.store.lock(session)?;
+        let before = self.get_commit(session, key)?;
+
+        let after = f(before.clone());
+        after.validate()?;
+
+        self.store.put_day(session, key, &after)?;
+
+        Ok((before, after))
     }
 
-    pub fn remove_working_commit(&self) -> Result<()> {
-        use std::fs::remove_file;
+    /// Deletes a committed day of the current month and returns it.
+    pub fn drop_commit(&self, session: &Session, key: &DayKey) -> Result<DayCommit> {
+        let _lock = self.store.lock(session)?;
+        let day_commit = self.get_commit(session, key)?;
+        self.store.remove_day(session, key)?;
+        Ok(day_commit)
+    }
 
-        let mut path = PathBuf::from(&self.data_path);
-        path.push("working.json");
+    /// A key names a day but not its month, so while the current month has
+    /// days of two this fails with `MixedMonths` instead of guessing.
+    fn get_commit(&self, session: &Session, key: &DayKey) -> Result<DayCommit> {
+        let days = match self.store.get_keyed_days(session) {
+            Err(Error(ErrorKind::NotInitialized, _)) => vec![],
+            r => r?,
+        };
+        if let Some((_, first)) = days.first() {
+            let Date(year, month, _) = first.date;
+            if days.iter()
+                .any(|(_, d)| d.date.0 != year || d.date.1 != month)
+            {
+                bail!(ErrorKind::MixedMonths(year, month));
+            }
+        }
+        match days.into_iter().find(|(k, _)| k == key) {
+            Some((_, day_commit)) => Ok(self.zoned(session, day_commit)),
+            None => bail!(ErrorKind::NoSuchDay(key.to_string())),
+        }
+    }
 
-        if !path.exists() {
+    pub fn get_working_directory_commit(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        Ok(self.zoned_all(session, self.store.get_days(session)?))
+    }
+
+    /// Refuses with `WorkingOpen` while a shift is still running on any branch.
+    pub fn push_a_month(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let _lock = self.store.lock(session)?;
+        if self.is_working(session)? {
+            bail!(ErrorKind::WorkingOpen);
+        }
+        self.store.push_month(session)
+    }
+
+    /// What `push_a_month` would archive, without touching anything.
+    pub fn push_preview(&self, session: &Session) -> Result<PushPreview> {
+        let days = self.store.get_days(session)?;
+        if days.is_empty() {
             bail!(ErrorKind::NotInitialized);
         }
-        remove_file(path).map_err(|e| ErrorKind::Io(e).into())
+        Ok(PushPreview {
+            months: group_by_month(days),
+            working_open: self.is_working(session)?,
+        })
     }
 
-    pub fn commit_a_day(&self, end_time: Time, message: String) -> Result<DayCommit> {
-        let mut day_commit: DayCommit = self.get_working_commit()?;
+    fn is_working(&self, session: &Session) -> Result<bool> {
+        Ok(!self.store.get_branches(session)?.is_empty())
+    }
 
-        day_commit.end_time = Some(end_time);
-        day_commit.message = Some(message);
+    pub fn get_archived_month_commit(
+        &self,
+        session: &Session,
+        year: i32,
+        month: u32,
+    ) -> Result<Vec<DayCommit>> {
+        Ok(self.zoned_all(session, self.store.get_month(session, year, month)?))
+    }
 
-        let mut path = PathBuf::from(&self.data_path);
-        path.push("working");
-        create_dir_all(&path)?;
-        path.push(day_commit.date.2.to_string());
-        path.set_extension("json");
+    pub fn get_archived_months(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        self.store.get_months(session)
+    }
 
-        let mut i: usize = 1;
-        while path.exists() {
-            path.pop();
-            path.push(day_commit.date.2.to_string() + "_" + &i.to_string());
-            path.set_extension("json");
-            i += 1;
+    /// Reopens an archived month. Refuses if the current month already has
+    /// days from another month.
+    pub fn unpush_a_month(&self, session: &Session, year: i32, month: u32) -> Result<()> {
+        let _lock = self.store.lock(session)?;
+        let days = match self.store.get_days(session) {
+            Err(Error(ErrorKind::NotInitialized, _)) => vec![],
+            r => r?,
+        };
+        if days.iter().any(|d| d.date.0 != year || d.date.1 != month) {
+            bail!(ErrorKind::MixedMonths(year, month));
         }
+        self.store.unpush_month(session, year, month)
+    }
 
-        let commit_file = File::create(&path)?;
-        serde_json::to_writer_pretty(commit_file, &day_commit)?;
+    /// `day_commit` in the zone configured for `session` if it has none.
+    fn zoned(&self, session: &Session, mut day_commit: DayCommit) -> DayCommit {
+        if day_commit.timezone.is_none() {
+            day_commit.timezone = Some(self.settings.timezone_name(session).to_owned());
+        }
+        day_commit
+    }
 
-        self.remove_working_commit()?;
+    fn zoned_all(&self, session: &Session, commits: Vec<DayCommit>) -> Vec<DayCommit> {
+        commits
+            .into_iter()
+            .map(|day_commit| self.zoned(session, day_commit))
+            .collect()
+    }
+}
 
-        Ok(day_commit)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store::MemoryStore;
+
+    fn app(timezone: &str) -> App {
+        let settings = ::serde_json::from_str(&format!(
+            r#"{{"api_token": "", "data_path": "/tmp/", "timezone": "{}"}}"#,
+            timezone
+        )).unwrap();
+        App::with_store(settings, Box::new(MemoryStore::new()))
     }
 
-    pub fn get_working_directory_commit(&self) -> Result<Vec<DayCommit>> {
-        let dir = self.get_working_directory_entries()?;
-        Ok(
-            dir.into_iter()
-                .filter_map(|f| {
-                    File::open(f.path())
-                        .ok()
-                        .and_then(|f| App::get_commit_from_file(&f).ok())
-                })
-                .collect(),
-        )
+    fn session() -> Session {
+        Session {
+            team_id: "T1".to_owned(),
+            channel_id: "C1".to_owned(),
+        }
     }
 
-    pub fn get_working_directory_entries(&self) -> Result<Vec<::std::fs::DirEntry>> {
-        use std::fs::read_dir;
+    fn shift(date: Date, start_time: Time, timezone: Option<&str>) -> DayCommit {
+        DayCommit {
+            date,
+            start_time,
+            end_time: None,
+            end_date: None,
+            message: Some("test".to_owned()),
+            participants: vec![],
+            timezone: timezone.map(|tz| tz.to_owned()),
+            breaks: vec![],
+            stashed_at: None,
+            stashed_branch: None,
+        }
+    }
 
-        let mut path = PathBuf::from(&self.data_path);
-        path.push("working");
-        if !path.exists() {
-            bail!(ErrorKind::NotInitialized);
+    #[test]
+    fn zoneless_record_read_in_configured_zone() {
+        let app = app("America/New_York");
+        let mut day_commit = shift(Date(2018, 3, 11), Time(1, 0), None);
+        day_commit.set_end_time(&"4:00".parse().unwrap());
+        assert_eq!(day_commit.duration().unwrap(), Some(TimeDiff(180)));
+        app.store.commit_day(&session(), &day_commit).unwrap();
+        let days = app.get_working_directory_commit(&session()).unwrap();
+        assert_eq!(days[0].timezone, Some("America/New_York".to_owned()));
+        assert_eq!(days[0].duration().unwrap(), Some(TimeDiff(120)));
+    }
+
+    fn at(date: Date, time: Time) -> NaiveDateTime {
+        date.at(&time)
+    }
+
+    fn break_of(start: NaiveDateTime, end: NaiveDateTime) -> Break {
+        Break {
+            start_date: start.date().into(),
+            start_time: start.time().into(),
+            end_date: Some(end.date().into()),
+            end_time: Some(end.time().into()),
+            reason: None,
         }
+    }
 
-        Ok(read_dir(path)?.filter_map(|d| d.ok()).collect())
+    #[test]
+    fn time_diff_between() {
+        let start = at(Date(2018, 3, 2), Time(22, 30));
+        assert_eq!(TimeDiff::between(&start, &start).unwrap(), TimeDiff(0));
+        let end = at(Date(2018, 3, 2), Time(23, 45));
+        assert_eq!(TimeDiff::between(&start, &end).unwrap(), TimeDiff(75));
+        let end = at(Date(2018, 3, 3), Time(1, 15));
+        assert_eq!(TimeDiff::between(&start, &end).unwrap(), TimeDiff(165));
     }
 
-    pub fn push_a_month(&self) -> Result<()> {
-        use std::fs::{copy, remove_file};
+    #[test]
+    fn time_diff_between_negative() {
+        let start = at(Date(2018, 3, 2), Time(10, 0));
+        let end = at(Date(2018, 3, 2), Time(9, 59));
+        match TimeDiff::between(&start, &end) {
+            Err(Error(ErrorKind::NegativeDuration(ref start, ref end), _)) => {
+                assert_eq!((start.as_str(), end.as_str()), ("3/2 10:0", "3/2 9:59"));
+            }
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn breaks_merged_and_clamped() {
+        let date = Date(2018, 3, 2);
+        let mut day_commit = shift(date.clone(), Time(9, 0), Some("UTC"));
+        day_commit.set_end_time(&"18:00".parse().unwrap());
+        day_commit.breaks = vec![
+            // before the start
+            break_of(at(date.clone(), Time(8, 0)), at(date.clone(), Time(9, 30))),
+            break_of(at(date.clone(), Time(12, 0)), at(date.clone(), Time(13, 0))),
+            // inside the one above
+            break_of(at(date.clone(), Time(12, 15)), at(date.clone(), Time(12, 45))),
+            // past the end
+            break_of(at(date.clone(), Time(17, 30)), at(date.clone(), Time(19, 0))),
+        ];
+        assert_eq!(day_commit.duration().unwrap(), Some(TimeDiff(540 - 30 - 60 - 30)));
+
+        let mut p = Participant::new("a".to_owned(), at(date.clone(), Time(9, 0)));
+        p.check_out(at(date.clone(), Time(18, 0)));
+        // overlapping the break of the shift from 12:00
+        p.breaks = vec![break_of(at(date.clone(), Time(12, 30)), at(date.clone(), Time(13, 30)))];
+        let end = day_commit.end().unwrap();
+        assert_eq!(
+            day_commit.break_ranges(Some(&p), &end),
+            vec![
+                (at(date.clone(), Time(8, 0)), at(date.clone(), Time(9, 30))),
+                (at(date.clone(), Time(12, 0)), at(date.clone(), Time(13, 30))),
+                (at(date.clone(), Time(17, 30)), at(date.clone(), Time(19, 0))),
+            ]
+        );
+        assert_eq!(p.worked(&day_commit, &end).unwrap(), TimeDiff(540 - 30 - 90 - 30));
+    }
 
-        let dir = self.get_working_directory_entries()?;
-        let first_day: DayCommit = App::get_commit_from_path(dir[0].path())?;
+    #[test]
+    fn overnight_times() {
+        let start = Date(2018, 3, 2);
+        let next = Date(2018, 3, 3);
+        let day_commit = shift(start.clone(), Time(22, 0), Some("UTC"));
+        assert_eq!(day_commit.at(&Time(23, 0)), at(start.clone(), Time(23, 0)));
+        assert_eq!(day_commit.at(&Time(6, 0)), at(next.clone(), Time(6, 0)));
 
-        let mut path = PathBuf::from(&self.data_path);
-        path.push(first_day.date.0.to_string());
-        path.push(first_day.date.1.to_string());
-        create_dir_all(&path)?;
+        let now = at(next.clone(), Time(7, 0));
+        let end = day_commit.at_before(&Time(6, 0), &now).unwrap();
+        assert_eq!(end, at(next.clone(), Time(6, 0)));
 
-        for d in dir {
-            let origin = d.path();
-            let mut target = path.clone();
-            target.push(origin.file_name().unwrap());
-            copy(&origin, target)?;
-            remove_file(origin)?;
+        let now = at(start.clone(), Time(22, 30));
+        match day_commit.at_before(&Time(23, 0), &now) {
+            Err(Error(ErrorKind::FutureTime(_), _)) => {}
+            r => panic!("{:?}", r),
         }
+        // 21:00 would be tomorrow, so it is taken as before the start
+        match day_commit.at_before(&Time(21, 0), &now) {
+            Err(Error(ErrorKind::NegativeDuration(_, _), _)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
 
-        Ok(())
+    #[test]
+    fn overnight_shift_across_dst() {
+        let tz = Some("America/New_York");
+        let mut day_commit = shift(Date(2018, 3, 10), Time(22, 0), tz);
+        day_commit.set_end_time(&"6:00+1".parse().unwrap());
+        assert_eq!(day_commit.end(), Some(at(Date(2018, 3, 11), Time(6, 0))));
+        // clocks went forward at 2:00
+        assert_eq!(day_commit.duration().unwrap(), Some(TimeDiff(7 * 60)));
+
+        let mut day_commit = shift(Date(2018, 11, 3), Time(22, 0), tz);
+        day_commit.set_end_time(&"6:00+1".parse().unwrap());
+        // and back at 2:00
+        assert_eq!(day_commit.duration().unwrap(), Some(TimeDiff(9 * 60)));
+    }
+
+    #[test]
+    fn stashed_time_not_worked() {
+        let app = app("UTC");
+        let date = Date(2018, 3, 2);
+        let mut day_commit = shift(date.clone(), Time(9, 0), Some("UTC"));
+        day_commit
+            .participants
+            .push(Participant::new("a".to_owned(), at(date.clone(), Time(9, 0))));
+        // as if it was stashed at 12:00 and is popped now
+        let evening = Some("evening");
+        app.store.create_working(&session(), evening, &day_commit).unwrap();
+        let stashed_at = at(date.clone(), Time(12, 0));
+        Break::start_in(&mut day_commit.breaks, stashed_at, Some("보관".to_owned())).unwrap();
+        day_commit.stashed_at = Some((date.clone(), Time(12, 0)));
+        app.store.stash_working(&session(), evening, &day_commit).unwrap();
+
+        let popped = app.pop_stashed_commit(&session(), evening, 0).unwrap();
+        assert!(popped.stashed_at.is_none() && popped.breaks[0].end().is_some());
+        // popped at 15:00 rather than now
+        app.edit_working_commit(&session(), evening, |mut day_commit| {
+            day_commit.breaks[0].end_date = Some(date.clone());
+            day_commit.breaks[0].end_time = Some(Time(15, 0));
+            Ok(day_commit)
+        }).unwrap();
+
+        let message = "test".to_owned();
+        let day_commit = app.commit_a_day(&session(), evening, Some(Time(18, 0)), message)
+            .unwrap();
+        let end = at(date.clone(), Time(18, 0));
+        // 9:00 ~ 18:00 without 12:00 ~ 15:00 in the stash
+        assert_eq!(day_commit.duration().unwrap(), Some(TimeDiff(9 * 60 - 3 * 60)));
+        let worked = day_commit.participants[0].worked(&day_commit, &end).unwrap();
+        assert_eq!(worked, TimeDiff(9 * 60 - 3 * 60));
     }
 }
@@ -0,0 +1,339 @@
+use std::str::FromStr;
+
+use error::{Error, ErrorKind};
+
+/// A `--flag` a command accepts. `value` names what follows it, like
+/// `"HH:MM"`, and is `None` for a flag without a value.
+pub struct Flag {
+    pub name: &'static str,
+    pub value: Option<&'static str>,
+    /// What it does, for `glt help <command>`
+    pub about: &'static str,
+}
+
+#[derive(Debug)]
+pub enum ArgError {
+    /// A quote is never closed
+    UnclosedQuote,
+    /// A flag the command doesn't know, or can't take along with the others
+    UnexpectedFlag(String),
+    /// A flag, and what should have followed it as the last word
+    MissingValue(&'static str, &'static str),
+    UnexpectedWord(String),
+    MissingWord(&'static str),
+    /// What a value should have been, and the value
+    InvalidValue(&'static str, String),
+}
+
+pub type ArgResult<T> = Result<T, ArgError>;
+
+impl From<ArgError> for Error {
+    fn from(e: ArgError) -> Error {
+        ErrorKind::InvalidArgument(e).into()
+    }
+}
+
+/// A word of a command, and whether any of it was quoted
+#[derive(Debug, Default, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub quoted: bool,
+}
+
+/// Splits `s` into words like a shell does. `"..."`, `'...'` and `“...”` keep
+/// whitespace in a word, but only open at the start of a word or right after
+/// an `=`, so apostrophes as in `don't` stay as they are.
+pub fn tokenize(s: &str) -> ArgResult<Vec<Token>> {
+    let mut words = vec![];
+    let mut word: Option<Token> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let opens = match word {
+            None => true,
+            Some(ref w) => w.text.ends_with('='),
+        };
+        let close = match c {
+            '"' | '\'' if opens => Some(c),
+            '“' if opens => Some('”'),
+            c if c.is_whitespace() => {
+                if let Some(w) = word.take() {
+                    words.push(w);
+                }
+                continue;
+            }
+            c => {
+                word.get_or_insert_with(Token::default).text.push(c);
+                continue;
+            }
+        };
+        let word = word.get_or_insert_with(Token::default);
+        word.quoted = true;
+        loop {
+            match chars.next() {
+                Some(c) if Some(c) == close => break,
+                Some(c) => word.text.push(c),
+                None => return Err(ArgError::UnclosedQuote),
+            }
+        }
+    }
+    if let Some(w) = word {
+        words.push(w);
+    }
+    Ok(words)
+}
+
+/// Words of a command with the flags taken out. A lone `--` ends the flags,
+/// everything after it is a word, and so is a quoted `"--word"`.
+pub struct Args {
+    pub words: Vec<String>,
+    flags: Vec<(&'static str, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse(tokens: Vec<Token>, flags: &[Flag]) -> ArgResult<Args> {
+        let mut args = Args {
+            words: vec![],
+            flags: vec![],
+        };
+        let mut tokens = tokens.into_iter();
+        while let Some(Token { text: token, quoted }) = tokens.next() {
+            if token == "--" && !quoted {
+                args.words.extend(tokens.map(|t| t.text));
+                break;
+            }
+            if quoted || !token.starts_with("--") {
+                args.words.push(token);
+                continue;
+            }
+            let flag = match flags.iter().find(|f| token[2..] == *f.name) {
+                Some(flag) => flag,
+                None => return Err(ArgError::UnexpectedFlag(token)),
+            };
+            let value = match flag.value {
+                Some(what) => match tokens.next() {
+                    Some(value) => Some(value.text),
+                    None => return Err(ArgError::MissingValue(flag.name, what)),
+                },
+                None => None,
+            };
+            args.flags.push((flag.name, value));
+        }
+        Ok(args)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|&(n, _)| n == name)
+    }
+
+    /// The last value given to `name`.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).pop()
+    }
+
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.flags
+            .iter()
+            .filter(|&&(n, _)| n == name)
+            .filter_map(|(_, v)| v.as_ref().map(|v| v.as_str()))
+            .collect()
+    }
+
+    /// The last value given to `name`, parsed. `what` is what it should be,
+    /// like `"HH:MM"`.
+    pub fn parse_value<T>(&self, name: &str, what: &'static str) -> ArgResult<Option<T>>
+    where
+        T: FromStr,
+    {
+        match self.value(name) {
+            Some(v) => parse(v, what).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Fails with `UnexpectedFlag` if any of `names` was given.
+    pub fn forbid(&self, names: &[&str]) -> ArgResult<()> {
+        match names.iter().find(|name| self.has(name)) {
+            Some(name) => Err(ArgError::UnexpectedFlag(format!("--{}", name))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn no_words(&self) -> ArgResult<()> {
+        match self.words.first() {
+            Some(word) => Err(ArgError::UnexpectedWord(word.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// The only word, if there is one, parsed.
+    pub fn optional_word<T: FromStr>(&self, what: &'static str) -> ArgResult<Option<T>> {
+        if let Some(word) = self.words.get(1) {
+            return Err(ArgError::UnexpectedWord(word.clone()));
+        }
+        match self.words.first() {
+            Some(word) => parse(word, what).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The only word, parsed.
+    pub fn word<T: FromStr>(&self, what: &'static str) -> ArgResult<T> {
+        match self.optional_word(what)? {
+            Some(value) => Ok(value),
+            None => Err(ArgError::MissingWord(what)),
+        }
+    }
+
+    /// Every word joined by spaces, `None` if there is none.
+    pub fn rest(&self) -> Option<String> {
+        if self.words.is_empty() {
+            None
+        } else {
+            Some(self.words.join(" "))
+        }
+    }
+}
+
+pub fn parse<T: FromStr>(s: &str, what: &'static str) -> ArgResult<T> {
+    s.parse().map_err(|_| ArgError::InvalidValue(what, s.to_owned()))
+}
+
+/// The candidate closest to `word`, if it is only a typo away.
+pub fn did_you_mean<'a, I>(word: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(word, c), c))
+        .filter(|&(d, c)| d <= 2 && d < c.chars().count())
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + ::std::cmp::min(diagonal, ::std::cmp::min(above, row[j]))
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAGS: &[Flag] = &[
+        Flag {
+            name: "at",
+            value: Some("HH:MM"),
+            about: "",
+        },
+        Flag {
+            name: "amend",
+            value: None,
+            about: "",
+        },
+    ];
+
+    fn texts(s: &str) -> Vec<String> {
+        tokenize(s).unwrap().into_iter().map(|t| t.text).collect()
+    }
+
+    fn parse(s: &str) -> ArgResult<Args> {
+        Args::parse(tokenize(s).unwrap(), FLAGS)
+    }
+
+    #[test]
+    fn tokenize_quotes() {
+        assert_eq!(texts(r#"add "김 민수" '이 영희'"#), ["add", "김 민수", "이 영희"]);
+        assert_eq!(texts("commit “긴 메시지”"), ["commit", "긴 메시지"]);
+        assert_eq!(texts("  a \t b  "), ["a", "b"]);
+    }
+
+    #[test]
+    fn tokenize_quote_after_equals() {
+        assert_eq!(texts(r#"edit 3 msg="a b" end=18:00"#), ["edit", "3", "msg=a b", "end=18:00"]);
+    }
+
+    #[test]
+    fn tokenize_keeps_apostrophes() {
+        assert_eq!(texts("commit don't stop"), ["commit", "don't", "stop"]);
+    }
+
+    #[test]
+    fn tokenize_unclosed_quote() {
+        match tokenize(r#"commit "never closed"#) {
+            Err(ArgError::UnclosedQuote) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn parse_flags_and_words() {
+        let args = parse("--at 9:30 fix --amend").unwrap();
+        assert_eq!(args.words, ["fix"]);
+        assert_eq!(args.value("at"), Some("9:30"));
+        assert!(args.has("amend"));
+    }
+
+    #[test]
+    fn parse_double_dash_ends_flags() {
+        let args = parse("--amend -- --at --amend").unwrap();
+        assert_eq!(args.words, ["--at", "--amend"]);
+        assert!(args.value("at").is_none());
+    }
+
+    #[test]
+    fn parse_quoted_flag_is_a_word() {
+        let args = parse(r#"fixed "--at" '--verbose'"#).unwrap();
+        assert_eq!(args.words, ["fixed", "--at", "--verbose"]);
+        assert!(args.value("at").is_none());
+    }
+
+    #[test]
+    fn parse_unknown_flag() {
+        match parse("--verbose") {
+            Err(ArgError::UnexpectedFlag(ref flag)) if flag == "--verbose" => {}
+            r => panic!("{:?}", r.map(|a| a.words)),
+        }
+    }
+
+    #[test]
+    fn parse_missing_value() {
+        match parse("--at") {
+            Err(ArgError::MissingValue("at", "HH:MM")) => {}
+            r => panic!("{:?}", r.map(|a| a.words)),
+        }
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("근무", "근무"), 0);
+    }
+
+    #[test]
+    fn did_you_mean_threshold() {
+        let commands = ["commit", "status", "stash", "st"];
+        assert_eq!(did_you_mean("comit", commands.iter().cloned()), Some("commit"));
+        assert_eq!(did_you_mean("stsh", commands.iter().cloned()), Some("stash"));
+        // three edits away is too far
+        assert_eq!(did_you_mean("cmt", commands.iter().cloned()), None);
+        // a suggestion can't be all edits
+        assert_eq!(did_you_mean("xy", commands.iter().cloned()), None);
+    }
+}
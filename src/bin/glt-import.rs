@@ -0,0 +1,23 @@
+extern crate glt;
+
+use glt::store::{FsJsonStore, SqliteStore};
+use glt::Settings;
+
+/// Loads an existing `data_path` tree into the configured SQLite database.
+/// Data from before sessions existed goes into the default session, the way
+/// the JSON store would move it.
+fn main() {
+    let settings = Settings::assure_new();
+    let store = match SqliteStore::open(settings.sqlite_path(), &settings.default_session()) {
+        Ok(s) => s,
+        Err(e) => panic!("SQLite open error!, {}", e),
+    };
+    let source = FsJsonStore::new(settings.data_path.clone());
+    if let Err(e) = source.migrate_legacy(&settings.default_session()) {
+        panic!("Legacy data migration error!, {}", e);
+    }
+    match store.import(&source) {
+        Ok(count) => println!("Imported {} day commits", count),
+        Err(e) => panic!("Import error!, {}", e),
+    }
+}
@@ -1,19 +1,38 @@
+use args::ArgError;
+
 error_chain! {
     foreign_links {
         Io(::std::io::Error);
         Config(::config::ConfigError);
         Json(::serde_json::error::Error);
         Request(::reqwest::Error);
+        Sqlite(::rusqlite::Error);
     }
     errors {
         Poisoned(a: &'static str) {
             description("lock poisoned")
             display("Lock poisoned at {}", a)
         }
+        InvalidSetting(key: &'static str, value: String) {
+            description("invalid setting")
+            display("Invalid setting {}: {}", key, value)
+        }
+        GitFailed(command: String, stderr: String) {
+            description("git command failed")
+            display("git {} failed: {}", command, stderr)
+        }
         InvalidToken {
             description("invalid token")
             display("Invalid token")
         }
+        InvalidSignature {
+            description("invalid signature")
+            display("Invalid request signature")
+        }
+        StaleRequest(timestamp: i64) {
+            description("stale request")
+            display("Request signed at {} is too old", timestamp)
+        }
         InvalidSubmission {
             description("invalid submission")
             display("Invalid submission")
@@ -22,6 +41,10 @@ error_chain! {
             description("command not found")
             display("No such command: {}", c)
         }
+        InvalidArgument(e: ArgError) {
+            description("invalid argument")
+            display("Invalid argument: {:?}", e)
+        }
         AlreadyInitialized {
             description("already initialized")
             display("Already initialized")
@@ -30,5 +53,53 @@ error_chain! {
             description("not initialized")
             display("Not initialized")
         }
+        WorkingOpen {
+            description("working shift open")
+            display("A shift is still running")
+        }
+        BrokenRecord(path: String) {
+            description("unreadable record")
+            display("Can't read record {}", path)
+        }
+        NoSuchDay(key: String) {
+            description("no such day")
+            display("No committed day {}", key)
+        }
+        NoSuchStash(n: usize) {
+            description("no such stash")
+            display("No stashed shift {}", n)
+        }
+        NotArchived(year: i32, month: u32) {
+            description("month not archived")
+            display("{}-{:02} is not archived", year, month)
+        }
+        MixedMonths(year: i32, month: u32) {
+            description("months would be mixed")
+            display("Current month has days outside {}-{:02}", year, month)
+        }
+        NegativeDuration(start: String, end: String) {
+            description("negative duration")
+            display("{} ends before it starts at {}", end, start)
+        }
+        FutureTime(at: String) {
+            description("time in the future")
+            display("{} has not come yet", at)
+        }
+        DurationOverflow {
+            description("duration overflow")
+            display("Duration overflow")
+        }
+        PermissionDenied {
+            description("permission denied")
+            display("Permission denied")
+        }
+        WorkerStopped {
+            description("worker stopped")
+            display("Background worker has stopped")
+        }
+        ResponseFailed(status: String) {
+            description("response_url rejected the response")
+            display("response_url answered {}", status)
+        }
     }
 }
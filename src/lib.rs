@@ -1,14 +1,19 @@
 #![feature(plugin, custom_derive, decl_macro)]
 #![plugin(rocket_codegen)]
+#![recursion_limit = "128"]
 extern crate chrono;
+extern crate chrono_tz;
 extern crate config;
 #[macro_use]
 extern crate error_chain;
+extern crate fs2;
 #[macro_use]
 extern crate lazy_static;
 extern crate reqwest;
+extern crate ring;
 extern crate rocket;
 extern crate rocket_contrib;
+extern crate rusqlite;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -17,208 +22,1016 @@ extern crate serde_json;
 pub mod error;
 pub use error::{Error, ErrorKind, Result};
 
+pub mod settings;
+pub use settings::{MessageFormat, Settings};
+
+pub mod store;
+pub use store::Store;
+
 pub mod app;
-pub use app::{App, Date, DayCommit, Participant, Time, TimeDiff};
+pub use app::{App, Break, Date, DayCommit, DayKey, EndTime, Interval, MonthCommits, Participant,
+              PushPreview, Session, Time, TimeDiff};
 
 pub mod slack;
 
+pub mod args;
+
+pub mod worker;
+pub use worker::{Job, Worker};
+
+use std::str::FromStr;
+
+use args::{tokenize, ArgError, ArgResult, Args, Flag};
+use chrono::NaiveDateTime;
+use error_chain::ChainedError;
+use slack::interaction::{self, Payload};
+use slack::signing::Signature;
 use slack::slash_command::Request;
 use slack::Response;
 
 lazy_static! {
     static ref APP: App = App::assure_new();
+    static ref WORKER: Worker = Worker::start(&*APP, handle_command_with);
 }
 
-#[derive(Clone)]
-enum Command {
-    Init,
-    Add,
-    Remove,
-    Status,
-    Commit,
-    Reset,
-    Log,
-    Push,
-    Help,
-}
-
-impl From<String> for Command {
-    fn from(s: String) -> Command {
-        use Command::*;
-        if s.starts_with("init") {
-            Init
-        } else if s.starts_with("add") {
-            Add
-        } else if s.starts_with("rm") {
-            Remove
-        } else if s.starts_with("status") {
-            Status
-        } else if s.starts_with("commit") {
-            Commit
-        } else if s.starts_with("reset") {
-            Reset
-        } else if s.starts_with("log") {
-            Log
-        } else if s.starts_with("push") {
-            Push
-        } else {
-            Help
-        }
+/// A subcommand as both the parser and `glt help` see it.
+struct CommandSpec {
+    /// The name first, then its aliases
+    names: &'static [&'static str],
+    /// Arguments after the name and what they do, one line each
+    usages: &'static [(&'static str, &'static str)],
+    flags: &'static [Flag],
+    examples: &'static [&'static str],
+    /// Whether it may take longer than Slack waits, and should answer later
+    /// through `response_url`
+    delayed: bool,
+    run: fn(&App, &Request, Args) -> Result<Response>,
+}
+
+impl CommandSpec {
+    fn name(&self) -> &'static str {
+        self.names[0]
+    }
+
+    fn usage(&self) -> String {
+        self.usages
+            .iter()
+            .map(|&(args, about)| match args {
+                "" => format!("/glt {} # {}", self.name(), about),
+                args => format!("/glt {} {} # {}", self.name(), args, about),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+const BRANCH: Flag = Flag {
+    name: "branch",
+    value: Some("name"),
+    about: "그 이름의 근무에서 따로 실행",
+};
+
+const AT: Flag = Flag {
+    name: "at",
+    value: Some("HH:MM"),
+    about: "지금 대신 그 시간으로 기록",
+};
+
+const FOR: Flag = Flag {
+    name: "for",
+    value: Some("name"),
+    about: "그 근무자만, 여러 번 쓸 수 있음",
+};
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        names: &["init"],
+        usages: &[("[<HH:MM>] [--date <YYYY-MM-DD>]", "그 날의 근무 시작")],
+        flags: &[
+            BRANCH,
+            Flag {
+                name: "date",
+                value: Some("YYYY-MM-DD"),
+                about: "오늘 대신 그 날의 근무로 시작",
+            },
+        ],
+        examples: &["/glt init", "/glt init 09:30", "/glt init 22:00 --date 2018-03-01"],
+        delayed: false,
+        run: init_command,
+    },
+    CommandSpec {
+        names: &["add"],
+        usages: &[(
+            "<name>[@<HH:MM>]...",
+            "온 사람 이름 추가, 퇴근한 사람은 다시 출근",
+        )],
+        flags: &[BRANCH],
+        examples: &["/glt add 민수 철수", "/glt add 민수@10:15", "/glt add \"김 민수\""],
+        delayed: false,
+        run: add_command,
+    },
+    CommandSpec {
+        names: &["rm", "remove"],
+        usages: &[("<name>...", "잘못 추가한 이름 제거")],
+        flags: &[BRANCH],
+        examples: &["/glt rm 민수"],
+        delayed: false,
+        run: rm_command,
+    },
+    CommandSpec {
+        names: &["out"],
+        usages: &[("<name>[@<HH:MM>]...", "간 사람 퇴근 기록")],
+        flags: &[BRANCH],
+        examples: &["/glt out 민수", "/glt out 민수@18:30 철수"],
+        delayed: false,
+        run: out_command,
+    },
+    CommandSpec {
+        names: &["pause"],
+        usages: &[(
+            "[--for <name>] [--at <HH:MM>] [reason]",
+            "휴식 시작, 이름이 없으면 모두",
+        )],
+        flags: &[BRANCH, FOR, AT],
+        examples: &["/glt pause 점심", "/glt pause --for 민수 --at 15:00 병원"],
+        delayed: false,
+        run: pause_command,
+    },
+    CommandSpec {
+        names: &["resume"],
+        usages: &[("[--for <name>] [--at <HH:MM>]", "휴식 끝, 근무 재개")],
+        flags: &[BRANCH, FOR, AT],
+        examples: &["/glt resume", "/glt resume --for 민수"],
+        delayed: false,
+        run: resume_command,
+    },
+    CommandSpec {
+        names: &["status", "st"],
+        usages: &[("", "그 날의 근무 기록 보기")],
+        flags: &[BRANCH],
+        examples: &["/glt status"],
+        delayed: false,
+        run: status_command,
+    },
+    CommandSpec {
+        names: &["commit", "ci"],
+        usages: &[
+            ("[--at <HH:MM>] <message>", "그 날의 근무 끝, 기록 추가"),
+            (
+                "--amend [--end <HH:MM>] [--add <name>] [--rm <name>] [message]",
+                "마지막 근무 기록 고치기",
+            ),
+        ],
+        flags: &[
+            BRANCH,
+            AT,
+            Flag {
+                name: "amend",
+                value: None,
+                about: "새로 기록하는 대신 마지막 근무 기록 고치기",
+            },
+            Flag {
+                name: "end",
+                value: Some("HH:MM[+1]"),
+                about: "--amend와 함께, 끝난 시간 고치기, 다음 날이면 +1",
+            },
+            Flag {
+                name: "add",
+                value: Some("name"),
+                about: "--amend와 함께, 근무자 추가",
+            },
+            Flag {
+                name: "rm",
+                value: Some("name"),
+                about: "--amend와 함께, 근무자 제거",
+            },
+        ],
+        examples: &[
+            "/glt commit 서버 점검",
+            "/glt commit --at 18:00 \"서버 점검, 배포\"",
+            "/glt commit --amend --end 18:30",
+        ],
+        delayed: false,
+        run: commit_command,
+    },
+    CommandSpec {
+        names: &["edit"],
+        usages: &[
+            (
+                "<day>[_n] start=<HH:MM> end=<HH:MM>[+1] msg=\"<message>\"",
+                "이번 달의 근무 기록 고치기, 다음 날 끝났으면 end에 +1",
+            ),
+            ("<day>[_n] add <name>...", "이번 달의 근무 기록에 근무자 추가"),
+            ("<day>[_n] rm <name>...", "이번 달의 근무 기록에서 근무자 제거"),
+        ],
+        flags: &[],
+        examples: &[
+            "/glt edit 2 end=18:00 msg=\"서버 점검\"",
+            "/glt edit 2 end=6:00+1",
+            "/glt edit 2_1 add 민수",
+        ],
+        delayed: false,
+        run: edit_command,
+    },
+    CommandSpec {
+        names: &["drop"],
+        usages: &[("<day>[_n]", "이번 달의 근무 기록 삭제")],
+        flags: &[],
+        examples: &["/glt drop 2", "/glt drop 2_1"],
+        delayed: false,
+        run: drop_command,
+    },
+    CommandSpec {
+        names: &["stash"],
+        usages: &[
+            ("", "그 날의 근무를 잠시 보관, 보관한 동안은 근무 시간에서 빠짐"),
+            ("list", "보관된 근무 목록"),
+            ("pop [<n>]", "보관된 근무를 원래 브랜치에서 다시 시작"),
+        ],
+        flags: &[BRANCH],
+        examples: &["/glt stash", "/glt stash list", "/glt stash pop 1"],
+        delayed: false,
+        run: stash_command,
+    },
+    CommandSpec {
+        names: &["reset"],
+        usages: &[("", "그 날의 근무 취소, 기록 버리기")],
+        flags: &[BRANCH],
+        examples: &["/glt reset"],
+        delayed: false,
+        run: reset_command,
+    },
+    CommandSpec {
+        names: &["log"],
+        usages: &[
+            ("", "그 달의 근무 기록 보기"),
+            ("<year>-<month>", "저장된 달의 근무 기록 보기"),
+            ("<year>", "그 해의 근무 기록 요약"),
+        ],
+        flags: &[],
+        examples: &["/glt log", "/glt log 2018-03", "/glt log 2018"],
+        delayed: true,
+        run: log_command,
+    },
+    CommandSpec {
+        names: &["months"],
+        usages: &[("", "근무 기록이 저장된 달 목록")],
+        flags: &[],
+        examples: &["/glt months"],
+        delayed: false,
+        run: months_command,
+    },
+    CommandSpec {
+        names: &["unpush"],
+        usages: &[("<year>-<month>", "저장된 달을 다시 열기 (관리자)")],
+        flags: &[],
+        examples: &["/glt unpush 2018-03"],
+        delayed: false,
+        run: unpush_command,
+    },
+    CommandSpec {
+        names: &["push"],
+        usages: &[
+            ("", "그 달의 근무 기록 저장 및 새 달로 넘어감"),
+            ("--dry-run", "저장될 근무 기록 미리 보기"),
+        ],
+        flags: &[Flag {
+            name: "dry-run",
+            value: None,
+            about: "저장하지 않고 미리 보기",
+        }],
+        examples: &["/glt push --dry-run", "/glt push"],
+        delayed: true,
+        run: push_command,
+    },
+    CommandSpec {
+        names: &["help"],
+        usages: &[("[<command>]", "도움말, 명령을 주면 그 명령의 자세한 사용법")],
+        flags: &[],
+        examples: &["/glt help", "/glt help commit"],
+        delayed: false,
+        run: help_command,
+    },
+];
+
+fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.names.contains(&name))
+}
+
+/// Verifies a request with its raw `body` and runs it.
+pub fn handle_command(
+    signature: Option<&Signature>,
+    body: &str,
+    data: Request,
+) -> Result<serde_json::Value> {
+    APP.verify(signature, body, &data.token)?;
+    if is_delayed(&data) {
+        return WORKER
+            .push(Job::Command(data))
+            .and_then(|_| Ok(serde_json::to_value(delayed_message())?))
+            .or_else(error_value);
+    }
+    handle_command_with(&APP, data)
+}
+
+/// Whether `data` runs a delayed command and can be answered later.
+pub fn is_delayed(data: &Request) -> bool {
+    if data.response_url.is_empty() {
+        return false;
+    }
+    let name = match tokenize(&data.text) {
+        Ok(words) => words.into_iter().next().map(|t| t.text),
+        Err(_) => None,
+    };
+    name.and_then(|name| find_command(&name))
+        .map_or(false, |spec| spec.delayed)
+}
+
+/// Runs a request that is already verified. Errors are logged and answered
+/// with a message carrying the ID they are logged under.
+pub fn handle_command_with(app: &App, data: Request) -> Result<serde_json::Value> {
+    let response = match run_command(app, &data) {
+        Ok(response) => response,
+        Err(e) => logged_error_message(&e),
+    };
+    serde_json::to_value(response).or_else(|e| error_value(e.into()))
+}
+
+fn run_command(app: &App, data: &Request) -> Result<Response> {
+    let mut words = match tokenize(&data.text) {
+        Ok(words) => words.into_iter(),
+        Err(e) => return Ok(arg_error_message(None, &e)),
+    };
+    let spec = match words.next() {
+        Some(name) => match find_command(&name.text) {
+            Some(spec) => spec,
+            None => return Ok(unknown_command_message(&name.text)),
+        },
+        None => find_command("help").unwrap(),
+    };
+
+    let response = Args::parse(words.collect(), spec.flags)
+        .map_err(Error::from)
+        .and_then(|args| (spec.run)(app, data, args));
+    match response {
+        Err(Error(ErrorKind::InvalidArgument(e), _)) => Ok(arg_error_message(Some(spec), &e)),
+        r => r,
     }
 }
 
-impl Command {
-    pub fn into_str(self) -> String {
-        self.into()
+/// Verifies an interactive request with its raw `body`, runs the action and
+/// has the worker update the message clicked on. Errors after that are
+/// answered through `response_url` like any other response.
+pub fn handle_action(
+    signature: Option<&Signature>,
+    body: &str,
+    form: &interaction::Form,
+) -> Result<()> {
+    let payload: Payload = match serde_json::from_str(&form.payload) {
+        Ok(payload) => payload,
+        Err(_) => bail!(ErrorKind::InvalidSubmission),
+    };
+    APP.verify(signature, body, &payload.token)?;
+    if payload.kind != "block_actions" {
+        bail!(ErrorKind::InvalidSubmission);
+    }
+    let url = payload.response_url.clone();
+    let response = handle_action_with(&APP, &payload).or_else(error_value)?;
+    if let Err(e) = WORKER.push(Job::Post(url.clone(), response)) {
+        // Without the worker the error is posted from here, only once
+        let message = error_value(e)?;
+        worker::post_response(&reqwest::Client::new(), &url, &message, 0)?;
     }
+    Ok(())
 }
 
-impl From<Command> for String {
-    fn from(c: Command) -> String {
-        use Command::*;
-        match c {
-            Init => "init",
-            Add => "add",
-            Remove => "rm",
-            Status => "status",
-            Commit => "commit",
-            Reset => "reset",
-            Log => "log",
-            Push => "push",
-            Help => "help",
-        }.to_owned()
+/// Runs an action that is already verified, answering with what replaces the
+/// message clicked on, or with a message of its own if it can't be run.
+pub fn handle_action_with(app: &App, payload: &Payload) -> Result<serde_json::Value> {
+    let (response, replace) = match run_action(app, payload) {
+        Ok(r) => r,
+        Err(e) => (logged_error_message(&e), false),
+    };
+    let mut value = serde_json::to_value(response)?;
+    value["replace_original"] = replace.into();
+    if replace {
+        // The message keeps being seen by whoever saw it
+        value["response_type"] = if payload.container.is_ephemeral {
+            "ephemeral"
+        } else {
+            "in_channel"
+        }.into();
     }
+    Ok(value)
 }
 
-pub fn handle_command(mut data: Request) -> Result<serde_json::Value> {
-    use Command::*;
+/// The response, and whether it replaces the message clicked on.
+fn run_action(app: &App, payload: &Payload) -> Result<(Response, bool)> {
+    let action = match payload.actions.first() {
+        Some(action) => action,
+        None => bail!(ErrorKind::InvalidSubmission),
+    };
+    let branch = action.value.as_ref().map(|b| b.as_str());
+    let session = payload.into();
+    let name = app.participant_name(&payload.user.id, &payload.user.username);
+    let format = app.settings.message_format;
+    let updated_status = |_: Vec<String>| -> Result<Response> {
+        status_message(status(app, &session, None)?, &app.now(&session)?, format)
+    };
+    let result = match action.action_id.as_str() {
+        "join" => add(app, &session, branch, vec![(name, None)]).and_then(&updated_status),
+        "leave" => out(app, &session, branch, vec![(name, None)]).and_then(&updated_status),
+        "commit" => commit(app, &session, branch, None, "버튼으로 끝낸 근무".to_owned())
+            .and_then(|day_commit| {
+                committed_message(
+                    day_commit,
+                    branch,
+                    "오늘의 근무가 끝났습니다. 수고하셨습니다!",
+                    format,
+                )
+            }),
+        _ => bail!(ErrorKind::InvalidSubmission),
+    };
+    match result {
+        Err(Error(ErrorKind::NotInitialized, _)) => Ok((not_initialized_message(), false)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok((negative_duration_message(&start, &end), false))
+        }
+        r => r.map(|response| (response, true)),
+    }
+}
 
-    let app = &APP;
+/// Seconds since the epoch and a counter in hex, like `5b9c4e2a-0003`, to find
+/// an error in the log from what the user saw.
+fn error_id() -> String {
+    use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+    static COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    format!("{:08x}-{:04x}", chrono::Utc::now().timestamp(), count & 0xffff)
+}
 
-    if !app.verify(&data.token) {
-        bail!(ErrorKind::InvalidToken);
+/// `--branch <name>`, where the name has nothing but letters, digits, `_`
+/// and `-`.
+fn branch_arg(args: &Args) -> ArgResult<Option<String>> {
+    let name = match args.value("branch") {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        Ok(Some(name.to_owned()))
+    } else {
+        Err(ArgError::InvalidValue("name", name.to_owned()))
     }
+}
 
-    let command: Command = data.text.clone().into();
-    data.text = data.text.replace(&command.clone().into_str(), "");
-    data.text = data.text.trim().to_owned();
+/// `[<HH:MM>] [--date <YYYY-MM-DD>]`
+struct Start {
+    date: Option<Date>,
+    time: Option<Time>,
+}
 
-    Ok(serde_json::to_value(match command {
-        Init => init_command,
-        Add => add_command,
-        Remove => rm_command,
-        Status => status_command,
-        Commit => commit_command,
-        Reset => reset_command,
-        Log => log_command,
-        Push => push_command,
-        Help => help_command,
-    }(app, &data)?)?)
+impl Start {
+    fn parse(args: &Args) -> ArgResult<Start> {
+        Ok(Start {
+            date: args.parse_value("date", "YYYY-MM-DD")?,
+            time: args.optional_word("HH:MM")?,
+        })
+    }
 }
 
-fn init_command(app: &App, _data: &Request) -> Result<Response> {
-    match init(app) {
-        Err(Error(ErrorKind::AlreadyInitialized, _)) => Ok(already_initialized_message()),
-        Ok(day_commit) => Ok(initialized_message(&day_commit)),
+fn init_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let start = Start::parse(&args)?;
+    match init(app, &data.into(), branch, start) {
+        Err(Error(ErrorKind::AlreadyInitialized, _)) => Ok(already_initialized_message(branch)),
+        Err(Error(ErrorKind::FutureTime(at), _)) => Ok(future_time_message(&at)),
+        Ok(day_commit) => Ok(initialized_message(
+            &day_commit,
+            branch,
+            app.settings.message_format,
+        )),
         Err(e) => Err(e),
     }
 }
 
-fn add_command(app: &App, data: &Request) -> Result<Response> {
-    let text = data.text.clone();
-    if text.is_empty() {
-        return Ok(invalid_argument_message());
+/// `민수` or `민수@10:15`
+fn parse_participant(s: &str) -> ArgResult<(String, Option<Time>)> {
+    let mut split = s.splitn(2, '@');
+    let name = split.next().unwrap_or_default();
+    if name.is_empty() {
+        return Err(ArgError::InvalidValue("name[@HH:MM]", s.to_owned()));
+    }
+    match split.next() {
+        Some(time) => Ok((name.to_owned(), Some(args::parse(time, "HH:MM")?))),
+        None => Ok((name.to_owned(), None)),
     }
-    let list: Vec<String> = text.split(' ').map(|s| s.to_owned()).collect();
-    if list.is_empty() {
-        return Ok(invalid_argument_message());
+}
+
+/// One or more `<name>[@<HH:MM>]` words
+fn participants_arg(args: &Args) -> ArgResult<Vec<(String, Option<Time>)>> {
+    if args.words.is_empty() {
+        return Err(ArgError::MissingWord("name"));
     }
-    match add(app, list) {
+    args.words.iter().map(|word| parse_participant(word)).collect()
+}
+
+fn add_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let list = participants_arg(&args)?;
+    match add(app, &data.into(), branch, list) {
         Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+        Err(Error(ErrorKind::FutureTime(at), _)) => Ok(future_time_message(&at)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
         Ok(added) => Ok(added_message(added)),
         Err(e) => Err(e),
     }
 }
 
-fn rm_command(app: &App, data: &Request) -> Result<Response> {
-    let text = data.text.clone();
-    if text.is_empty() {
-        return Ok(invalid_argument_message());
-    }
-    let list: Vec<String> = text.split(' ').map(|s| s.to_owned()).collect();
-    if list.is_empty() {
-        return Ok(invalid_argument_message());
+fn rm_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    if args.words.is_empty() {
+        bail!(ArgError::MissingWord("name"));
     }
-    match rm(app, list) {
+    match rm(app, &data.into(), branch, args.words) {
         Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
         Ok(_) => Ok(removed_message()),
         Err(e) => Err(e),
     }
 }
 
-fn status_command(app: &App, _data: &Request) -> Result<Response> {
-    match status(app) {
+fn out_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let list = participants_arg(&args)?;
+    match out(app, &data.into(), branch, list) {
         Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
-        Ok(day_commit) => Ok(status_message(day_commit)),
+        Err(Error(ErrorKind::FutureTime(at), _)) => Ok(future_time_message(&at)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        Ok(checked_out) => Ok(checked_out_message(checked_out)),
         Err(e) => Err(e),
     }
 }
 
-fn commit_command(app: &App, data: &Request) -> Result<Response> {
-    let text = data.text.clone();
-    if text.is_empty() {
-        return Ok(invalid_argument_message());
+/// `[--for <name>]... [--at <HH:MM>] [reason]`, without names the whole
+/// shift takes the break.
+struct Pause {
+    names: Vec<String>,
+    at: Option<Time>,
+    reason: Option<String>,
+}
+
+impl Pause {
+    fn parse(args: &Args) -> ArgResult<Pause> {
+        Ok(Pause {
+            names: args.values("for").into_iter().map(|n| n.to_owned()).collect(),
+            at: args.parse_value("at", "HH:MM")?,
+            reason: args.rest(),
+        })
+    }
+}
+
+fn pause_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let pause = Pause::parse(&args)?;
+    let session = data.into();
+    let result = if pause.names.is_empty() {
+        let reason = pause.reason.clone();
+        pause_shift(app, &session, branch, pause).map(|paused| if paused {
+            paused_message(reason)
+        } else {
+            already_paused_message()
+        })
+    } else {
+        pause_participants(app, &session, branch, pause).map(participants_paused_message)
+    };
+    match result {
+        Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+        Err(Error(ErrorKind::FutureTime(at), _)) => Ok(future_time_message(&at)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        r => r,
     }
-    match commit(app, text) {
+}
+
+/// `[--for <name>]... [--at <HH:MM>]`
+fn resume_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    args.no_words()?;
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let pause = Pause::parse(&args)?;
+    let session = data.into();
+    let result = if pause.names.is_empty() {
+        resume_shift(app, &session, branch, pause.at).map(|resumed| if resumed {
+            resumed_message()
+        } else {
+            not_paused_message()
+        })
+    } else {
+        resume_participants(app, &session, branch, pause.names, pause.at)
+            .map(participants_resumed_message)
+    };
+    match result {
         Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
-        Ok(day_commit) => Ok(committed_message(day_commit)),
-        Err(e) => Err(e),
+        Err(Error(ErrorKind::FutureTime(at), _)) => Ok(future_time_message(&at)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        r => r,
+    }
+}
+
+fn status_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    args.no_words()?;
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let session = data.into();
+    match status(app, &session, branch).and_then(|branches| {
+        status_message(branches, &app.now(&session)?, app.settings.message_format)
+    }) {
+        Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        r => r,
+    }
+}
+
+/// `[--branch <name>]`, `list` or `pop [<n>] [--branch <name>]`
+enum Stash {
+    Push,
+    List,
+    Pop(usize),
+}
+
+impl Stash {
+    fn parse(args: &Args) -> ArgResult<Stash> {
+        let mut words = args.words.iter().map(|w| w.as_str());
+        match (words.next(), words.next(), words.next()) {
+            (None, _, _) => Ok(Stash::Push),
+            (Some("list"), None, _) => {
+                args.forbid(&["branch"])?;
+                Ok(Stash::List)
+            }
+            (Some("pop"), None, _) => Ok(Stash::Pop(0)),
+            (Some("pop"), Some(n), None) => Ok(Stash::Pop(args::parse(n, "n")?)),
+            (Some("list"), Some(word), _) | (Some("pop"), _, Some(word)) => {
+                Err(ArgError::UnexpectedWord(word.to_owned()))
+            }
+            (Some(word), _, _) => Err(ArgError::InvalidValue("list|pop", word.to_owned())),
+        }
+    }
+}
+
+fn stash_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let session = data.into();
+    let subcommand = Stash::parse(&args)?;
+    // A popped shift goes back to the branch it came from unless one is named
+    let branch = match (branch_arg(&args)?, &subcommand) {
+        (None, &Stash::Pop(n)) => stash_list(app, &session)?
+            .into_iter()
+            .nth(n)
+            .and_then(|day_commit| day_commit.stashed_branch),
+        (branch, _) => branch,
+    };
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let result = match subcommand {
+        Stash::Push => {
+            stash(app, &session, branch).map(|day_commit| stashed_message(&day_commit, branch))
+        }
+        Stash::List => stash_list(app, &session).map(|stashes| stash_list_message(&stashes)),
+        Stash::Pop(n) => pop_stash(app, &session, branch, n)
+            .map(|day_commit| popped_message(&day_commit, branch)),
+    };
+    match result {
+        Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+        Err(Error(ErrorKind::AlreadyInitialized, _)) => Ok(already_initialized_message(branch)),
+        Err(Error(ErrorKind::NoSuchStash(n), _)) => Ok(no_such_stash_message(n)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        r => r,
+    }
+}
+
+/// `--amend [--end <HH:MM>[+1]] [--add <name>]... [--rm <name>]... [message]`
+struct Amend {
+    message: Option<String>,
+    end_time: Option<EndTime>,
+    add: Vec<String>,
+    rm: Vec<String>,
+}
+
+impl Amend {
+    fn parse(args: &Args) -> ArgResult<Amend> {
+        args.forbid(&["branch", "at"])?;
+        let amend = Amend {
+            message: args.rest(),
+            end_time: args.parse_value("end", "HH:MM[+1]")?,
+            add: args.values("add").into_iter().map(|n| n.to_owned()).collect(),
+            rm: args.values("rm").into_iter().map(|n| n.to_owned()).collect(),
+        };
+        if amend.message.is_none() && amend.end_time.is_none() && amend.add.is_empty()
+            && amend.rm.is_empty()
+        {
+            return Err(ArgError::MissingWord("message"));
+        }
+        Ok(amend)
+    }
+}
+
+fn commit_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    if args.has("amend") {
+        let amend = Amend::parse(&args)?;
+        return match amend_commit(app, &data.into(), amend).and_then(|day_commit| {
+            committed_message(
+                day_commit,
+                None,
+                "근무 기록이 수정되었습니다.",
+                app.settings.message_format,
+            )
+        }) {
+            Err(Error(ErrorKind::NotInitialized, _)) => Ok(no_record_message()),
+            Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+                Ok(negative_duration_message(&start, &end))
+            }
+            r => r,
+        };
+    }
+    args.forbid(&["end", "add", "rm"])?;
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    let end_time = args.parse_value("at", "HH:MM")?;
+    let message = args.rest().ok_or(ArgError::MissingWord("message"))?;
+    match commit(app, &data.into(), branch, end_time, message).and_then(|day_commit| {
+        committed_message(
+            day_commit,
+            branch,
+            "오늘의 근무가 끝났습니다. 수고하셨습니다!",
+            app.settings.message_format,
+        )
+    }) {
+        Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+        Err(Error(ErrorKind::FutureTime(at), _)) => Ok(future_time_message(&at)),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        r => r,
     }
 }
 
-fn reset_command(app: &App, _data: &Request) -> Result<Response> {
-    match reset(app) {
+fn reset_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    args.no_words()?;
+    let branch = branch_arg(&args)?;
+    let branch = branch.as_ref().map(|b| b.as_str());
+    match reset(app, &data.into(), branch) {
         Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
         Ok(()) => Ok(resetted_message()),
         Err(e) => Err(e),
     }
 }
 
-fn log_command(app: &App, _data: &Request) -> Result<Response> {
-    match log(app) {
-        Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
-        Ok(commits) => Ok(log_message(&commits)),
+enum LogRange {
+    Working,
+    Year(i32),
+    Month(i32, u32),
+}
+
+impl FromStr for LogRange {
+    type Err = ();
+
+    /// `"2018"` or `"2018-03"`
+    fn from_str(s: &str) -> ::std::result::Result<LogRange, ()> {
+        let mut split = s.splitn(2, '-');
+        let year: i32 = split.next().ok_or(())?.parse().map_err(|_| ())?;
+        match split.next() {
+            None => Ok(LogRange::Year(year)),
+            Some(month) => match month.parse() {
+                Ok(month) if month >= 1 && month <= 12 => Ok(LogRange::Month(year, month)),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+fn log_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let range = args.optional_word("YYYY[-MM]")?.unwrap_or(LogRange::Working);
+    let format = app.settings.message_format;
+    let session = data.into();
+    let response = match range {
+        LogRange::Working => match log(app, &session) {
+            Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+            Ok(commits) => log_message(&commits, "이 달의 근무 기록", format),
+            Err(e) => Err(e),
+        },
+        LogRange::Month(year, month) => {
+            let commits = archived_log(app, &session, year, month)?;
+            if commits.is_empty() {
+                Ok(no_record_message())
+            } else {
+                log_message(&commits, "지난 달의 근무 기록", format)
+            }
+        }
+        LogRange::Year(year) => {
+            let months = year_log(app, &session, year)?;
+            if months.is_empty() {
+                Ok(no_record_message())
+            } else {
+                year_log_message(year, &months, format)
+            }
+        }
+    };
+    match response {
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        r => r,
+    }
+}
+
+fn push_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    args.no_words()?;
+    let session = data.into();
+    if args.has("dry-run") {
+        match push_preview(app, &session) {
+            Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+            Ok(preview) => Ok(push_preview_message(&preview)),
+            Err(e) => Err(e),
+        }
+    } else {
+        match push(app, &session) {
+            Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
+            Err(Error(ErrorKind::WorkingOpen, _)) => Ok(working_open_message()),
+            Ok(months) => Ok(push_message(&months)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn unpush_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    if !app.is_admin(&data.user_id) {
+        return Ok(permission_denied_message());
+    }
+    let (year, month) = match args.word("YYYY-MM")? {
+        LogRange::Month(year, month) => (year, month),
+        _ => bail!(ArgError::InvalidValue("YYYY-MM", args.words[0].clone())),
+    };
+    match unpush(app, &data.into(), year, month) {
+        Err(Error(ErrorKind::NotArchived(..), _)) => Ok(no_record_message()),
+        Err(Error(ErrorKind::MixedMonths(..), _)) => Ok(mixed_months_message()),
+        Ok(()) => Ok(unpushed_message(year, month)),
         Err(e) => Err(e),
     }
 }
 
-fn push_command(app: &App, _data: &Request) -> Result<Response> {
-    match push(app) {
-        Err(Error(ErrorKind::NotInitialized, _)) => Ok(not_initialized_message()),
-        Ok(()) => Ok(push_message()),
+/// `<day>[_n] start=<HH:MM> end=<HH:MM>[+1] msg="<message>"`,
+/// `<day>[_n] add <name>...` or `<day>[_n] rm <name>...`
+enum Edit {
+    Set {
+        start_time: Option<Time>,
+        end_time: Option<EndTime>,
+        message: Option<String>,
+    },
+    Add(Vec<String>),
+    Remove(Vec<String>),
+}
+
+impl Edit {
+    fn parse(args: &Args) -> ArgResult<(DayKey, Edit)> {
+        let mut words = args.words.iter();
+        let key = match words.next() {
+            Some(key) => args::parse(key, "day[_n]")?,
+            None => return Err(ArgError::MissingWord("day[_n]")),
+        };
+        let edit = match words.next().map(|w| w.as_str()) {
+            Some("add") => Edit::Add(words.cloned().collect()),
+            Some("rm") => Edit::Remove(words.cloned().collect()),
+            Some(_) => {
+                let (mut start_time, mut end_time, mut message) = (None, None, None);
+                for word in &args.words[1..] {
+                    let mut split = word.splitn(2, '=');
+                    match (split.next(), split.next()) {
+                        (Some("start"), Some(v)) => start_time = Some(args::parse(v, "HH:MM")?),
+                        (Some("end"), Some(v)) => {
+                            end_time = Some(args::parse(v, "HH:MM[+1]")?)
+                        }
+                        (Some("msg"), Some(v)) => message = Some(v.to_owned()),
+                        _ => return Err(ArgError::UnexpectedWord(word.clone())),
+                    }
+                }
+                Edit::Set {
+                    start_time,
+                    end_time,
+                    message,
+                }
+            }
+            None => return Err(ArgError::MissingWord("start=|end=|msg=|add|rm")),
+        };
+        match edit {
+            Edit::Add(ref names) | Edit::Remove(ref names) if names.is_empty() => {
+                Err(ArgError::MissingWord("name"))
+            }
+            edit => Ok((key, edit)),
+        }
+    }
+}
+
+fn edit_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let (key, edit) = Edit::parse(&args)?;
+    match edit_commit(app, &data.into(), &key, edit) {
+        Err(Error(ErrorKind::NoSuchDay(_), _)) => Ok(no_such_day_message(&key)),
+        Err(Error(ErrorKind::MixedMonths(..), _)) => Ok(mixed_months_message()),
+        Err(Error(ErrorKind::NegativeDuration(start, end), _)) => {
+            Ok(negative_duration_message(&start, &end))
+        }
+        Ok((before, after)) => Ok(edited_message(&key, &before, &after)),
+        Err(e) => Err(e),
+    }
+}
+
+fn drop_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    let key: DayKey = args.word("day[_n]")?;
+    match drop_commit(app, &data.into(), &key) {
+        Err(Error(ErrorKind::NoSuchDay(_), _)) => Ok(no_such_day_message(&key)),
+        Err(Error(ErrorKind::MixedMonths(..), _)) => Ok(mixed_months_message()),
+        Ok(day_commit) => Ok(dropped_message(&key, &day_commit)),
         Err(e) => Err(e),
     }
 }
 
-fn help_command(_app: &App, _data: &Request) -> Result<Response> {
-    Ok(help_message())
+fn months_command(app: &App, data: &Request, args: Args) -> Result<Response> {
+    args.no_words()?;
+    let months = months(app, &data.into())?;
+    if months.is_empty() {
+        Ok(no_record_message())
+    } else {
+        Ok(months_message(&months))
+    }
+}
+
+fn help_command(_app: &App, _data: &Request, args: Args) -> Result<Response> {
+    match args.optional_word::<String>("command")? {
+        None => Ok(help_message()),
+        Some(name) => match find_command(&name) {
+            Some(spec) => Ok(command_help_message(spec)),
+            None => Ok(unknown_command_message(&name)),
+        },
+    }
+}
+
+/// `2018년 3월 2일`, or `2018년 3월 2일 evening` on a named branch.
+fn shift_title(date: &Date, branch: Option<&str>) -> String {
+    match branch {
+        Some(branch) => format!("{} {}", date, branch),
+        None => date.to_string(),
+    }
 }
 
-fn initialized_message(day_commit: &DayCommit) -> Response {
+/// With blocks, buttons to join, leave and end the shift come along.
+fn initialized_message(
+    day_commit: &DayCommit,
+    branch: Option<&str>,
+    format: MessageFormat,
+) -> Response {
     use slack::*;
-    Response::Message(Message {
-        response_type: ResponseType::InChannel,
-        text: format!("{} 근무 시작!", day_commit.date),
-        mrkdwn: false,
-    })
+    let text = format!("{} 근무 시작!", shift_title(&day_commit.date, branch));
+    match format {
+        MessageFormat::Attachments => Response::Message(Message {
+            response_type: ResponseType::InChannel,
+            text,
+            mrkdwn: false,
+        }),
+        MessageFormat::Blocks => Response::BlockMessage(BlockMessage {
+            response_type: ResponseType::InChannel,
+            blocks: vec![
+                Block::Section {
+                    text: Some(Text::plain(text.clone())),
+                    fields: vec![],
+                },
+                shift_actions(branch),
+            ],
+            text,
+        }),
+    }
 }
 
-fn already_initialized_message() -> Response {
+fn already_initialized_message(branch: Option<&str>) -> Response {
     use slack::*;
+    let reset = match branch {
+        Some(branch) => format!("glt reset --branch {}", branch),
+        None => "glt reset".to_owned(),
+    };
     Response::Message(Message {
         response_type: ResponseType::Ephemeral,
-        text: "이미 근무가 시작되었습니다.\n오늘의 근무를 취소하려면 `glt reset`"
-            .to_owned(),
+        text: format!("이미 근무가 시작되었습니다.\n오늘의 근무를 취소하려면 `{}`", reset),
         mrkdwn: true,
     })
 }
@@ -240,11 +1053,146 @@ fn added_message(added: Vec<String>) -> Response {
     })
 }
 
-fn invalid_argument_message() -> Response {
+/// What is wrong in Korean, like `` `--at` 뒤에 `<HH:MM>`이 필요합니다.``
+fn arg_error_str(spec: Option<&CommandSpec>, e: &ArgError) -> String {
+    match *e {
+        ArgError::UnclosedQuote => "따옴표가 닫히지 않았습니다.".to_owned(),
+        ArgError::UnexpectedFlag(ref flag) => {
+            let known = spec.map_or(false, |spec| spec.flags.iter().any(|f| flag[2..] == *f.name));
+            let similar = spec.and_then(|spec| {
+                args::did_you_mean(&flag[2..], spec.flags.iter().map(|f| f.name))
+            });
+            match (spec, similar) {
+                (Some(_), _) if known => {
+                    format!("`{}` 옵션은 다른 인자와 함께 쓸 수 없습니다.", flag)
+                }
+                (Some(spec), Some(similar)) => format!(
+                    "`{}`에는 `{}` 옵션을 쓸 수 없습니다. `--{}`을 찾으셨나요?",
+                    spec.name(),
+                    flag,
+                    similar
+                ),
+                (Some(spec), None) => {
+                    format!("`{}`에는 `{}` 옵션을 쓸 수 없습니다.", spec.name(), flag)
+                }
+                (None, _) => format!("`{}` 옵션을 쓸 수 없습니다.", flag),
+            }
+        }
+        ArgError::MissingValue(flag, what) => format!("`--{}` 뒤에 `<{}>`이 필요합니다.", flag, what),
+        ArgError::UnexpectedWord(ref word) => format!("`{}`은 필요 없는 인자입니다.", word),
+        ArgError::MissingWord(what) => format!("`<{}>`이 필요합니다.", what),
+        ArgError::InvalidValue(what, ref value) => {
+            format!("`{}`은 올바른 `<{}>`이 아닙니다.", value, what)
+        }
+    }
+}
+
+fn arg_error_message(spec: Option<&CommandSpec>, e: &ArgError) -> Response {
+    use slack::*;
+    let text = match spec {
+        Some(spec) => format!(
+            "{}\n{}\n자세히 보려면 `glt help {}`",
+            arg_error_str(Some(spec), e),
+            spec.usage(),
+            spec.name()
+        ),
+        None => format!("{}\n도움말을 보려면 `glt help`", arg_error_str(None, e)),
+    };
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text,
+        mrkdwn: true,
+    })
+}
+
+fn unknown_command_message(name: &str) -> Response {
+    use slack::*;
+    let names = COMMANDS.iter().flat_map(|spec| spec.names.iter().cloned());
+    let text = match args::did_you_mean(name, names) {
+        Some(similar) => format!(
+            "`{}`은 없는 명령입니다. `{}`을 찾으셨나요?\n도움말을 보려면 `glt help`",
+            name, similar
+        ),
+        None => format!("`{}`은 없는 명령입니다.\n도움말을 보려면 `glt help`", name),
+    };
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text,
+        mrkdwn: true,
+    })
+}
+
+/// What went wrong in a sentence, without the details that go to the log
+fn error_str(e: &Error) -> String {
+    match *e.kind() {
+        ErrorKind::Io(_) => "파일을 읽거나 쓰지 못했습니다.".to_owned(),
+        ErrorKind::Config(_) => "설정을 읽지 못했습니다.".to_owned(),
+        ErrorKind::Json(_) => "기록을 읽거나 쓰지 못했습니다.".to_owned(),
+        ErrorKind::Request(_) => "Slack에 요청을 보내지 못했습니다.".to_owned(),
+        ErrorKind::Sqlite(_) => "데이터베이스를 읽거나 쓰지 못했습니다.".to_owned(),
+        ErrorKind::Poisoned(_) => "다른 요청을 처리하다 서버에 문제가 생겼습니다.".to_owned(),
+        ErrorKind::InvalidSetting(key, _) => format!("서버 설정 `{}`이 잘못되었습니다.", key),
+        ErrorKind::GitFailed(ref command, _) => format!("`git {}`이 실패했습니다.", command),
+        ErrorKind::InvalidToken | ErrorKind::InvalidSignature | ErrorKind::StaleRequest(_) => {
+            "Slack에서 온 요청인지 확인하지 못했습니다.".to_owned()
+        }
+        ErrorKind::InvalidSubmission => "요청의 형식이 잘못되었습니다.".to_owned(),
+        ErrorKind::CommandNotFound(ref c) => format!("`{}`은 없는 명령입니다.", c),
+        ErrorKind::InvalidArgument(ref e) => arg_error_str(None, e),
+        ErrorKind::AlreadyInitialized => "근무가 이미 시작되었습니다.".to_owned(),
+        ErrorKind::NotInitialized => "근무가 시작되지 않았습니다.".to_owned(),
+        ErrorKind::WorkingOpen => "아직 끝나지 않은 근무가 있습니다.".to_owned(),
+        ErrorKind::BrokenRecord(ref path) => format!("기록 `{}`을 읽지 못했습니다.", path),
+        ErrorKind::NoSuchDay(ref key) => format!("{}의 근무 기록이 없습니다.", key),
+        ErrorKind::NoSuchStash(n) => format!("`{}`번 보관된 근무가 없습니다.", n),
+        ErrorKind::NotArchived(year, month) => {
+            format!("{}년 {}월의 근무 기록은 저장되지 않았습니다.", year, month)
+        }
+        ErrorKind::MixedMonths(year, month) => {
+            format!("이번 달에 {}년 {}월이 아닌 근무 기록이 있습니다.", year, month)
+        }
+        ErrorKind::NegativeDuration(ref start, ref end) => {
+            format!("`{}`은 `{}`보다 앞설 수 없습니다.", end, start)
+        }
+        ErrorKind::FutureTime(ref at) => format!("`{}`은 아직 오지 않은 시간입니다.", at),
+        ErrorKind::DurationOverflow => "근무 시간이 너무 깁니다.".to_owned(),
+        ErrorKind::PermissionDenied => "관리자만 사용할 수 있는 명령입니다.".to_owned(),
+        ErrorKind::WorkerStopped => "지금은 요청을 처리할 수 없습니다.".to_owned(),
+        _ => "알 수 없는 문제가 생겼습니다.".to_owned(),
+    }
+}
+
+/// `error_message` with a new error ID, logging `e` under it.
+fn logged_error_message(e: &Error) -> Response {
+    let id = error_id();
+    eprintln!("[{}] {}", id, e.display_chain());
+    error_message(e, &id)
+}
+
+/// `logged_error_message` as JSON, for errors of a request that is already
+/// verified, which get an answer rather than an HTTP error.
+fn error_value(e: Error) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(logged_error_message(&e))?)
+}
+
+fn error_message(e: &Error, id: &str) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: format!(
+            "{}\n계속 이런다면 관리자에게 오류 ID `{}`를 알려 주세요.",
+            error_str(e),
+            id
+        ),
+        mrkdwn: true,
+    })
+}
+
+fn delayed_message() -> Response {
     use slack::*;
     Response::Message(Message {
         response_type: ResponseType::Ephemeral,
-        text: "잘못된 인자 사용\n도움말을 보려면 `glt help`".to_owned(),
+        text: "처리 중입니다. 잠시만 기다려 주세요.".to_owned(),
         mrkdwn: true,
     })
 }
@@ -268,267 +1216,1388 @@ fn removed_message() -> Response {
     })
 }
 
-fn status_message(day_commit: DayCommit) -> Response {
+fn checked_out_message(checked_out: Vec<String>) -> Response {
     use slack::*;
-    let mut m = AttachedMessage {
-        response_type: ResponseType::Ephemeral,
-        attachments: vec![],
-    };
-    let mut a = Attachment {
-        title: day_commit.date.to_string(),
-        text: "".to_owned(),
-        pretext: "오늘의 근무 기록".to_owned(),
-        fields: vec![],
-        mrkdwn_in: vec![],
-    };
-    a.fields.push(AttachmentFields {
-        title: "시작 시간".to_owned(),
-        value: day_commit.start_time.to_string(),
-    });
-    a.fields.push(AttachmentFields {
-        title: "근무자".to_owned(),
-        value: {
-            let mut content = String::new();
-            for p in day_commit.participants {
-                let line = format!("{} - {}\n", p.name, p.commit_time);
-                content.push_str(&line);
-            }
-            content
-        },
-    });
-    m.attachments.push(a);
-    Response::AttachedMessage(m)
+    if checked_out.is_empty() {
+        return Response::Message(Message {
+            response_type: ResponseType::Ephemeral,
+            text: "퇴근할 근무자가 없습니다.".to_owned(),
+            mrkdwn: false,
+        });
+    }
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!("{} 근무자가 퇴근했습니다.", checked_out.join(", ")),
+        mrkdwn: false,
+    })
 }
 
-fn committed_message(day_commit: DayCommit) -> Response {
+fn paused_message(reason: Option<String>) -> Response {
     use slack::*;
-    let mut m = AttachedMessage {
+    Response::Message(Message {
         response_type: ResponseType::InChannel,
-        attachments: vec![],
-    };
-    let mut a = Attachment {
-        title: day_commit.date.to_string(),
-        text: "".to_owned(),
-        pretext: "오늘의 근무가 끝났습니다. 수고하셨습니다!".to_owned(),
-        fields: vec![],
-        mrkdwn_in: vec![],
-    };
-    a.fields.push(AttachmentFields {
-        title: "근무 시간".to_owned(),
-        value: {
-            let start_time = &day_commit.start_time;
-            let end_time = &day_commit.end_time.unwrap();
-            let diff = end_time - start_time;
-            format!(
-                "{} ~ {} {}",
-                start_time.to_short_str(),
-                end_time.to_short_str(),
-                diff,
-            )
+        text: match reason {
+            Some(reason) => format!("휴식 시작! ({})", reason),
+            None => "휴식 시작!".to_owned(),
         },
-    });
-    a.fields.push(AttachmentFields {
-        title: "근무 내용".to_owned(),
-        value: day_commit.message.unwrap(),
-    });
-    a.fields.push(AttachmentFields {
-        title: "근무자".to_owned(),
-        value: {
-            let mut content = String::new();
-            for p in day_commit.participants {
-                let line = format!("{} - {}\n", p.name, p.commit_time);
-                content.push_str(&line);
-            }
-            content
-        },
-    });
-    m.attachments.push(a);
-    Response::AttachedMessage(m)
+        mrkdwn: false,
+    })
 }
 
-fn resetted_message() -> Response {
+fn already_paused_message() -> Response {
     use slack::*;
     Response::Message(Message {
         response_type: ResponseType::Ephemeral,
-        text: "근무 기록이 삭제되었습니다.".to_owned(),
-        mrkdwn: false,
+        text: "이미 쉬는 중입니다.\n근무를 다시 시작하려면 `glt resume`".to_owned(),
+        mrkdwn: true,
     })
 }
 
-fn log_message(commits: &[DayCommit]) -> Response {
+fn resumed_message() -> Response {
     use slack::*;
-    use std::collections::HashMap;
-    let first_day = commits.first().unwrap();
-    let total_hour: Vec<f32> = commits
-        .into_iter()
-        .filter(|c| c.end_time.is_some())
-        .map(|c| (&c.end_time.clone().unwrap() - &c.start_time).into())
-        .collect();
-    let total_hour: f32 = total_hour.into_iter().sum();
-    let total_hour: Time = total_hour.into();
-    let mut participants_record: HashMap<String, (u32, f32)> = HashMap::new();
-
-    let mut m = AttachedMessage {
+    Response::Message(Message {
         response_type: ResponseType::InChannel,
-        attachments: vec![],
-    };
-    let mut a = Attachment {
-        title: format!("{}년 {}월", first_day.date.0, first_day.date.1),
-        text: format!(
-            "총 {}일, {}의 근무 기록이 있습니다.",
-            commits.len(),
-            total_hour
-        ),
-        pretext: "이 달의 근무 기록".to_owned(),
-        fields: vec![],
-        mrkdwn_in: vec!["fields".to_owned()],
-    };
-    for day_commit in commits {
-        a.fields.push(AttachmentFields {
-            title: format!("{}일", day_commit.date.2),
-            value: {
-                let mut s = String::new();
-                if let Some(ref end_time) = day_commit.end_time {
-                    let start_time = &day_commit.start_time;
-                    let diff = end_time - start_time;
-                    s = s
-                        + &format!(
-                            "{} ~ {} {}",
-                            start_time.to_short_str(),
-                            end_time.to_short_str(),
-                            diff,
-                        );
-                } else {
-                    s = s + &format!("{} 시작", day_commit.start_time);
-                }
-                if let Some(ref msg) = day_commit.message {
-                    s = s + "\n" + msg;
-                }
-                if !day_commit.participants.is_empty() {
-                    s += "\n";
-                    for p in &day_commit.participants {
-                        s = s + &p.name + ", ";
-
-                        let entry = participants_record
-                            .entry(p.name.clone())
-                            .or_insert((0u32, 0f32));
-                        entry.0 += 1;
-                        if let Some(ref end_time) = day_commit.end_time {
-                            let d: f32 = (end_time - &p.commit_time).into();
-                            entry.1 += d;
-                        }
-                    }
-                    s.pop();
-                    s.pop();
-                }
-                s
-            },
+        text: "근무 재개!".to_owned(),
+        mrkdwn: false,
+    })
+}
+
+fn not_paused_message() -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: "쉬는 중이 아닙니다.\n휴식을 시작하려면 `glt pause`".to_owned(),
+        mrkdwn: true,
+    })
+}
+
+fn participants_paused_message(paused: Vec<String>) -> Response {
+    use slack::*;
+    if paused.is_empty() {
+        return Response::Message(Message {
+            response_type: ResponseType::Ephemeral,
+            text: "휴식을 시작할 근무자가 없습니다.".to_owned(),
+            mrkdwn: false,
         });
     }
-    if !participants_record.is_empty() {
-        a.fields.push(AttachmentFields {
-            title: "총계".to_owned(),
-            value: {
-                let mut s = format!(
-                    "총 {}일, {}의 근무 시간 중",
-                    commits.len(),
-                    total_hour
-                );
-                for (k, v) in participants_record {
-                    let t: TimeDiff = v.1.into();
-                    s = s + &format!("\n{} - {}일, {}", k, v.0, t);
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!("{} 근무자가 휴식을 시작했습니다.", paused.join(", ")),
+        mrkdwn: false,
+    })
+}
+
+fn participants_resumed_message(resumed: Vec<String>) -> Response {
+    use slack::*;
+    if resumed.is_empty() {
+        return Response::Message(Message {
+            response_type: ResponseType::Ephemeral,
+            text: "근무를 재개할 근무자가 없습니다.".to_owned(),
+            mrkdwn: false,
+        });
+    }
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!("{} 근무자가 근무를 재개했습니다.", resumed.join(", ")),
+        mrkdwn: false,
+    })
+}
+
+fn breaks_str(breaks: &[Break], date: &Date) -> String {
+    breaks
+        .iter()
+        .map(|b| b.to_str(date))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// `name - 9:30 ~ 12:0, 13:0 ~ (7시간 0분)`, open stays count until `until`.
+/// Their own breaks follow as `, 휴식 15:0 ~ 15:30`.
+fn participant_line(
+    day_commit: &DayCommit,
+    p: &Participant,
+    until: &NaiveDateTime,
+) -> Result<String> {
+    let worked = p.worked(day_commit, until)?;
+    let mut line = format!("{} - {} ({})", p.name, p.stays_str(&day_commit.date), worked);
+    if !p.breaks.is_empty() {
+        line = line + ", 휴식 " + &breaks_str(&p.breaks, &day_commit.date);
+    }
+    Ok(line + "\n")
+}
+
+/// A context with `pretext` if there is one, a section with `title` and
+/// `text`, and sections of `fields`, cut to what Slack takes.
+fn record_blocks(
+    pretext: &str,
+    title: &str,
+    text: &str,
+    fields: &[(String, String)],
+) -> Vec<slack::Block> {
+    use slack::*;
+    let mut blocks = vec![];
+    if !pretext.is_empty() {
+        blocks.push(Block::context(&escape(pretext)));
+    }
+    let mut section = format!("*{}*", escape(title));
+    if !text.is_empty() {
+        section = section + "\n" + &escape(text);
+    }
+    blocks.push(Block::text(&section));
+    blocks.extend(Block::fields(
+        fields
+            .iter()
+            .map(|(title, value)| format!("*{}*\n{}", escape(title), escape(value)))
+            .collect(),
+    ));
+    blocks
+}
+
+fn attachment_fields(fields: Vec<(String, String)>) -> Vec<slack::AttachmentFields> {
+    fields
+        .into_iter()
+        .map(|(title, value)| slack::AttachmentFields { title, value })
+        .collect()
+}
+
+/// `blocks` as a message, those past `MAX_BLOCKS` left out with a note.
+fn block_message(
+    response_type: slack::ResponseType,
+    text: &str,
+    mut blocks: Vec<slack::Block>,
+) -> Response {
+    use slack::*;
+    if blocks.len() > MAX_BLOCKS {
+        let left_out = blocks.len() - (MAX_BLOCKS - 1);
+        blocks.truncate(MAX_BLOCKS - 1);
+        blocks.push(Block::context(&format!(
+            "메시지가 길어 나머지 블록 {}개를 생략했습니다.",
+            left_out
+        )));
+    }
+    Response::BlockMessage(BlockMessage {
+        response_type,
+        text: text.to_owned(),
+        blocks,
+    })
+}
+
+/// One attachment for every running shift. With blocks, every shift comes
+/// with buttons to join, leave and end it.
+fn status_message(
+    branches: Vec<(Option<String>, DayCommit)>,
+    now: &NaiveDateTime,
+    format: MessageFormat,
+) -> Result<Response> {
+    use slack::*;
+    let pretext = "오늘의 근무 기록";
+    let mut attachments = vec![];
+    let mut blocks = vec![];
+    for (i, (branch, day_commit)) in branches.into_iter().enumerate() {
+        let branch = branch.as_ref().map(|b| b.as_str());
+        let title = shift_title(&day_commit.date, branch);
+        let fields = status_fields(&day_commit, now)?;
+        let pretext = if i == 0 { pretext } else { "" };
+        match format {
+            MessageFormat::Attachments => attachments.push(Attachment {
+                title,
+                text: "".to_owned(),
+                pretext: pretext.to_owned(),
+                fields: attachment_fields(fields),
+                mrkdwn_in: vec![],
+            }),
+            MessageFormat::Blocks => {
+                if i > 0 {
+                    blocks.push(Block::Divider);
                 }
-                s
-            },
+                blocks.extend(record_blocks(pretext, &title, "", &fields));
+                blocks.push(shift_actions(branch));
+            }
+        }
+    }
+    Ok(match format {
+        MessageFormat::Attachments => Response::AttachedMessage(AttachedMessage {
+            response_type: ResponseType::Ephemeral,
+            attachments,
+        }),
+        MessageFormat::Blocks => block_message(ResponseType::Ephemeral, pretext, blocks),
+    })
+}
+
+/// Buttons to join, leave and end the shift on `branch`, which they carry
+/// as their value. Ending it asks first.
+fn shift_actions(branch: Option<&str>) -> slack::Block {
+    use slack::*;
+    let button = |action_id: &str, label: &str, style, confirm| Element::Button {
+        text: Text::plain(label),
+        action_id: action_id.to_owned(),
+        value: branch.map(|b| b.to_owned()),
+        style,
+        confirm,
+    };
+    let confirm = Confirm {
+        title: Text::plain("근무 끝"),
+        text: Text::plain("근무를 끝내고 기록할까요? 근무 내용은 \"버튼으로 끝낸 근무\"로 남습니다."),
+        confirm: Text::plain("끝내기"),
+        deny: Text::plain("취소"),
+    };
+    Block::Actions {
+        elements: vec![
+            button("join", "출근", Some(ButtonStyle::Primary), None),
+            button("leave", "퇴근", None, None),
+            button("commit", "근무 끝", Some(ButtonStyle::Danger), Some(confirm)),
+        ],
+    }
+}
+
+fn status_fields(day_commit: &DayCommit, now: &NaiveDateTime) -> Result<Vec<(String, String)>> {
+    let mut fields = vec![("시작 시간".to_owned(), day_commit.start_time.to_string())];
+    if !day_commit.breaks.is_empty() {
+        fields.push((
+            "휴식".to_owned(),
+            breaks_str(&day_commit.breaks, &day_commit.date),
+        ));
+    }
+    let mut participants = String::new();
+    for p in &day_commit.participants {
+        participants.push_str(&participant_line(day_commit, p, now)?);
+    }
+    fields.push(("근무자".to_owned(), participants));
+    Ok(fields)
+}
+
+fn committed_message(
+    day_commit: DayCommit,
+    branch: Option<&str>,
+    pretext: &str,
+    format: MessageFormat,
+) -> Result<Response> {
+    use slack::*;
+    let title = shift_title(&day_commit.date, branch);
+    let end = day_commit.end().unwrap();
+    let mut fields = vec![(
+        "근무 시간".to_owned(),
+        format!(
+            "{} {}",
+            day_commit.hours_str(),
+            day_commit.duration()?.unwrap()
+        ),
+    )];
+    if !day_commit.breaks.is_empty() {
+        fields.push((
+            "휴식".to_owned(),
+            breaks_str(&day_commit.breaks, &day_commit.date),
+        ));
+    }
+    fields.push(("근무 내용".to_owned(), day_commit.message.clone().unwrap()));
+    let mut participants = String::new();
+    for p in &day_commit.participants {
+        participants.push_str(&participant_line(&day_commit, p, &end)?);
+    }
+    fields.push(("근무자".to_owned(), participants));
+    Ok(match format {
+        MessageFormat::Attachments => Response::AttachedMessage(AttachedMessage {
+            response_type: ResponseType::InChannel,
+            attachments: vec![Attachment {
+                title,
+                text: "".to_owned(),
+                pretext: pretext.to_owned(),
+                fields: attachment_fields(fields),
+                mrkdwn_in: vec![],
+            }],
+        }),
+        MessageFormat::Blocks => {
+            let blocks = record_blocks(pretext, &title, "", &fields);
+            block_message(ResponseType::InChannel, pretext, blocks)
+        }
+    })
+}
+
+fn stashed_message(day_commit: &DayCommit, branch: Option<&str>) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!(
+            "{} 근무를 보관했습니다.\n다시 시작하려면 `glt stash pop`",
+            shift_title(&day_commit.date, branch)
+        ),
+        mrkdwn: true,
+    })
+}
+
+/// `` `0` 2018년 3월 2일 evening 9:0 ~ 14:30 (민수, 영희)``, the latest first,
+/// with the branch it was stashed from. The time it was stashed ends the
+/// range, or the start of its last break in shifts that didn't record it.
+fn stash_list_message(stashes: &[DayCommit]) -> Response {
+    use slack::*;
+    if stashes.is_empty() {
+        return Response::Message(Message {
+            response_type: ResponseType::Ephemeral,
+            text: "보관된 근무가 없습니다.".to_owned(),
+            mrkdwn: false,
         });
     }
-    m.attachments.push(a);
-    Response::AttachedMessage(m)
+    let mut s = "보관된 근무".to_owned();
+    for (n, day_commit) in stashes.iter().enumerate() {
+        let branch = day_commit.stashed_branch.as_ref().map(|b| b.as_str());
+        s = s + &format!(
+            "\n`{}` {} {} ~",
+            n,
+            shift_title(&day_commit.date, branch),
+            day_commit.start_time.to_short_str()
+        );
+        let stashed_at = match day_commit.stashed_at {
+            Some((ref date, ref time)) => Some(date.at(time)),
+            None => day_commit.breaks.last().map(|b| b.start()),
+        };
+        if let Some(ref at) = stashed_at {
+            s = s + " " + &day_commit.date.short_str(at);
+        }
+        if !day_commit.participants.is_empty() {
+            s = s + &format!(" ({})", participant_names(day_commit));
+        }
+    }
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: s,
+        mrkdwn: true,
+    })
+}
+
+fn popped_message(day_commit: &DayCommit, branch: Option<&str>) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!(
+            "{} 근무를 다시 시작합니다.",
+            shift_title(&day_commit.date, branch)
+        ),
+        mrkdwn: false,
+    })
+}
+
+fn no_such_stash_message(n: usize) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: format!(
+            "보관된 근무 `{}`이 없습니다.\n보관된 근무를 보려면 `glt stash list`",
+            n
+        ),
+        mrkdwn: true,
+    })
+}
+
+fn resetted_message() -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: "근무 기록이 삭제되었습니다.".to_owned(),
+        mrkdwn: false,
+    })
+}
+
+fn total_hour(commits: &[DayCommit]) -> Result<TimeDiff> {
+    let mut total_hour = TimeDiff(0);
+    for day_commit in commits {
+        if let Some(diff) = day_commit.duration()? {
+            total_hour = total_hour.checked_add(diff)?;
+        }
+    }
+    Ok(total_hour)
+}
+
+/// Name to (days, hours)
+fn participants_record(
+    commits: &[DayCommit],
+) -> Result<::std::collections::HashMap<String, (u32, TimeDiff)>> {
+    use std::collections::HashMap;
+    let mut participants_record: HashMap<String, (u32, TimeDiff)> = HashMap::new();
+    for day_commit in commits {
+        for p in &day_commit.participants {
+            let entry = participants_record
+                .entry(p.name.clone())
+                .or_insert((0u32, TimeDiff(0)));
+            entry.0 += 1;
+            if let Some(ref end) = day_commit.end() {
+                entry.1 = entry.1.checked_add(p.worked(day_commit, end)?)?;
+            }
+        }
+    }
+    Ok(participants_record)
+}
+
+fn participants_record_field(
+    days: usize,
+    total_hour: TimeDiff,
+    commits: &[DayCommit],
+) -> Result<Option<(String, String)>> {
+    let participants_record = participants_record(commits)?;
+    if participants_record.is_empty() {
+        return Ok(None);
+    }
+    let mut s = format!("총 {}일, {}의 근무 시간 중", days, total_hour);
+    for (k, v) in participants_record {
+        s = s + &format!("\n{} - {}일, {}", k, v.0, v.1);
+    }
+    Ok(Some(("총계".to_owned(), s)))
+}
+
+/// A summary of `commits` under `title`, one field for every part of it.
+fn summary_message(
+    pretext: &str,
+    title: String,
+    commits: &[DayCommit],
+    total_hour: TimeDiff,
+    mut fields: Vec<(String, String)>,
+    format: MessageFormat,
+) -> Result<Response> {
+    use slack::*;
+    let text = format!(
+        "총 {}일, {}의 근무 기록이 있습니다.",
+        commits.len(),
+        total_hour
+    );
+    if let Some(field) = participants_record_field(commits.len(), total_hour, commits)? {
+        fields.push(field);
+    }
+    Ok(match format {
+        MessageFormat::Attachments => Response::AttachedMessage(AttachedMessage {
+            response_type: ResponseType::InChannel,
+            attachments: vec![Attachment {
+                title,
+                text,
+                pretext: pretext.to_owned(),
+                fields: attachment_fields(fields),
+                mrkdwn_in: vec!["fields".to_owned()],
+            }],
+        }),
+        MessageFormat::Blocks => {
+            let blocks = record_blocks(pretext, &title, &text, &fields);
+            block_message(ResponseType::InChannel, pretext, blocks)
+        }
+    })
+}
+
+fn log_message(commits: &[DayCommit], pretext: &str, format: MessageFormat) -> Result<Response> {
+    let first_day = commits.first().unwrap();
+    let mut fields = vec![];
+    for day_commit in commits {
+        let mut s = String::new();
+        if let Some(diff) = day_commit.duration()? {
+            s = s + &format!("{} {}", day_commit.hours_str(), diff);
+        } else {
+            s = s + &format!("{} 시작", day_commit.start_time);
+        }
+        if let Some(ref msg) = day_commit.message {
+            s = s + "\n" + msg;
+        }
+        if !day_commit.participants.is_empty() {
+            s = s + "\n" + &participant_names(day_commit);
+        }
+        fields.push((format!("{}일", day_commit.date.2), s));
+    }
+    summary_message(
+        pretext,
+        format!("{}년 {}월", first_day.date.0, first_day.date.1),
+        commits,
+        total_hour(commits)?,
+        fields,
+        format,
+    )
+}
+
+fn year_log_message(
+    year: i32,
+    months: &[MonthCommits],
+    format: MessageFormat,
+) -> Result<Response> {
+    let commits: Vec<DayCommit> = months
+        .iter()
+        .flat_map(|(_, commits)| commits.iter().cloned())
+        .collect();
+    let mut fields = vec![];
+    for &((_, month), ref month_commits) in months {
+        let month_hour = total_hour(month_commits)?;
+        fields.push((
+            format!("{}월", month),
+            format!("{}일, {}", month_commits.len(), month_hour),
+        ));
+    }
+    summary_message(
+        "한 해의 근무 기록",
+        format!("{}년", year),
+        &commits,
+        total_hour(&commits)?,
+        fields,
+        format,
+    )
+}
+
+fn months_message(months: &[(i32, u32)]) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: {
+            let mut s = "저장된 근무 기록".to_owned();
+            for &(year, month) in months {
+                s = s + &format!("\n{}년 {}월", year, month);
+            }
+            s
+        },
+        mrkdwn: false,
+    })
 }
 
-fn push_message() -> Response {
+fn unpushed_message(year: i32, month: u32) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!("{}년 {}월의 근무 기록을 다시 열었습니다.", year, month),
+        mrkdwn: false,
+    })
+}
+
+fn mixed_months_message() -> Response {
     use slack::*;
     Response::Message(Message {
         response_type: ResponseType::Ephemeral,
-        text: "이 달의 근무가 끝났습니다. 수고하셨습니다!".to_owned(),
+        text: "이번 달에 다른 달의 근무 기록이 있습니다.\n먼저 `glt push`".to_owned(),
+        mrkdwn: true,
+    })
+}
+
+fn permission_denied_message() -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: "관리자만 사용할 수 있는 명령입니다.".to_owned(),
         mrkdwn: false,
     })
 }
 
-fn help_message() -> Response {
+fn participant_names(day_commit: &DayCommit) -> String {
+    let names: Vec<&str> = day_commit
+        .participants
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
+    names.join(", ")
+}
+
+fn edited_message(key: &DayKey, before: &DayCommit, after: &DayCommit) -> Response {
+    use slack::*;
+    fn end_str(day_commit: &DayCommit) -> String {
+        day_commit
+            .end()
+            .map_or("-".to_owned(), |end| day_commit.date.short_str(&end))
+    }
+    let mut lines: Vec<(&str, String, String)> = vec![];
+    lines.push((
+        "시작 시간",
+        before.start_time.to_short_str(),
+        after.start_time.to_short_str(),
+    ));
+    lines.push((
+        "끝난 시간",
+        end_str(before),
+        end_str(after),
+    ));
+    lines.push((
+        "근무 내용",
+        before.message.clone().unwrap_or_default(),
+        after.message.clone().unwrap_or_default(),
+    ));
+    lines.push(("근무자", participant_names(before), participant_names(after)));
+
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: {
+            let mut s = format!("{} ({}) 근무 기록이 수정되었습니다.", after.date, key);
+            for (title, b, a) in lines {
+                if b != a {
+                    s = s + &format!("\n{}: `{}` → `{}`", title, b, a);
+                }
+            }
+            s
+        },
+        mrkdwn: true,
+    })
+}
+
+fn dropped_message(key: &DayKey, day_commit: &DayCommit) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::InChannel,
+        text: format!(
+            "{} ({}) 근무 기록이 삭제되었습니다.\n`{}` {}\n{}",
+            day_commit.date,
+            key,
+            day_commit.hours_str(),
+            day_commit.message.clone().unwrap_or_default(),
+            participant_names(day_commit),
+        ),
+        mrkdwn: true,
+    })
+}
+
+fn no_such_day_message(key: &DayKey) -> Response {
     use slack::*;
     Response::Message(Message {
         response_type: ResponseType::Ephemeral,
-        text: "/glt init # 그 날의 근무 시작
-/glt add <name> # 온 사람 이름 추가
-/glt rm <name> # 온 사람 이름 제거
-/glt status # 그 날의 근무 기록 보기
-/glt commit <message> # 그 날의 근무 끝, 기록 추가
-/glt reset # 그 날의 근무 취소, 기록 버리기
-/glt log # 그 달의 근무 기록 보기
-/glt push # 그 달의 근무 기록 저장 및 새 달로 넘어감"
+        text: format!(
+            "이번 달에 {}의 근무 기록이 없습니다.\n근무 기록을 보려면 `glt log`",
+            key
+        ),
+        mrkdwn: true,
+    })
+}
+
+fn negative_duration_message(start: &str, end: &str) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: format!("`{}`은 `{}`보다 앞설 수 없습니다.", end, start),
+        mrkdwn: true,
+    })
+}
+
+fn future_time_message(at: &str) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: format!("`{}`은 아직 오지 않은 시간입니다.", at),
+        mrkdwn: true,
+    })
+}
+
+fn no_record_message() -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: "근무 기록이 없습니다.\n저장된 달을 보려면 `glt months`".to_owned(),
+        mrkdwn: true,
+    })
+}
+
+fn push_message(months: &[(i32, u32)]) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: {
+            let mut s = String::new();
+            for &(year, month) in months {
+                s = s + &format!("{}년 {}월, ", year, month);
+            }
+            s.pop();
+            s.pop();
+            s + "의 근무가 끝났습니다. 수고하셨습니다!"
+        },
+        mrkdwn: false,
+    })
+}
+
+fn push_preview_message(preview: &PushPreview) -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: {
+            let mut s = "`glt push`를 하면 다음 근무 기록이 저장됩니다.".to_owned();
+            for &((year, month), ref commits) in &preview.months {
+                s = s + &format!("\n{}년 {}월 - ", year, month);
+                for day_commit in commits {
+                    s = s + &format!("{}일, ", day_commit.date.2);
+                }
+                s.pop();
+                s.pop();
+            }
+            if preview.working_open {
+                s += "\n아직 끝나지 않은 근무가 있어 지금은 저장할 수 없습니다.";
+            }
+            s
+        },
+        mrkdwn: true,
+    })
+}
+
+fn working_open_message() -> Response {
+    use slack::*;
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: "아직 끝나지 않은 근무가 있습니다.\n\
+               근무를 끝내려면 `glt commit <message>`, 취소하려면 `glt reset`"
             .to_owned(),
+        mrkdwn: true,
+    })
+}
+
+fn help_message() -> Response {
+    use slack::*;
+    let mut lines: Vec<String> = COMMANDS.iter().map(|spec| spec.usage()).collect();
+    let branched: Vec<&str> = COMMANDS
+        .iter()
+        .filter(|spec| spec.flags.iter().any(|f| f.name == "branch"))
+        .map(|spec| spec.name())
+        .collect();
+    lines.push(format!(
+        "{} 뒤에 --branch <name>을 붙이면 그 이름의 근무에서 따로 실행",
+        branched.join(", ")
+    ));
+    lines.push("공백이 들어간 값이나 --로 시작하는 단어는 \"김 민수\"처럼 따옴표로 묶기".to_owned());
+    let aliases: Vec<String> = COMMANDS
+        .iter()
+        .flat_map(|spec| {
+            spec.names[1..]
+                .iter()
+                .map(move |alias| format!("{} = {}", alias, spec.name()))
+        })
+        .collect();
+    lines.push(format!("줄여 쓰기: {}", aliases.join(", ")));
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: lines.join("\n"),
         mrkdwn: false,
     })
 }
 
-fn init(app: &App) -> Result<DayCommit> {
-    use chrono::prelude::*;
-    app.create_working_file(Local::today().into(), Local::now().time().into())
+fn command_help_message(spec: &CommandSpec) -> Response {
+    use slack::*;
+    let mut lines = vec![spec.usage()];
+    if spec.names.len() > 1 {
+        lines.push(format!("다른 이름: {}", spec.names[1..].join(", ")));
+    }
+    if !spec.flags.is_empty() {
+        lines.push("\n옵션".to_owned());
+        lines.extend(spec.flags.iter().map(|flag| match flag.value {
+            Some(value) => format!("--{} <{}> # {}", flag.name, value, flag.about),
+            None => format!("--{} # {}", flag.name, flag.about),
+        }));
+    }
+    lines.push("\n예".to_owned());
+    lines.extend(spec.examples.iter().map(|example| example.to_string()));
+    Response::Message(Message {
+        response_type: ResponseType::Ephemeral,
+        text: lines.join("\n"),
+        mrkdwn: false,
+    })
 }
 
-fn add(app: &App, participants: Vec<String>) -> Result<Vec<String>> {
-    let now: Time = chrono::Local::now().time().into();
-    let mut added: Vec<String> = vec![];
+fn init(app: &App, session: &Session, branch: Option<&str>, start: Start) -> Result<DayCommit> {
+    let now = app.now(session)?;
+    app.create_working_file(
+        session,
+        branch,
+        start.date.unwrap_or_else(|| now.date().into()),
+        start.time.unwrap_or_else(|| now.time().into()),
+    )
+}
 
-    app.edit_working_commit(|mut day_commit| {
-        let cloned_commit = day_commit.clone();
+/// Participants who checked out come back in with a new stay. Times are
+/// checked to be between the start of the shift, or their last check-out,
+/// and now.
+fn add(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    participants: Vec<(String, Option<Time>)>,
+) -> Result<Vec<String>> {
+    let now = app.now(session)?;
+    let mut added: Vec<String> = vec![];
 
-        for p in participants {
-            let pp = Participant {
-                commit_time: now.clone(),
-                name: p,
+    app.edit_working_commit(session, branch, |mut day_commit| {
+        for (p, time) in participants {
+            let at = match time {
+                Some(time) => day_commit.at_before(&time, &now)?,
+                None => now,
             };
-            if !cloned_commit.participants.contains(&pp) {
-                added.push(pp.name.clone());
-                day_commit.participants.push(pp);
+            match day_commit.participants.iter().position(|dp| dp.name == p) {
+                Some(i) => {
+                    let last_out = day_commit.participants[i]
+                        .stays()
+                        .last()
+                        .and_then(|stay| stay.out_at(&day_commit.date));
+                    if let Some(ref last_out) = last_out {
+                        day_commit.elapsed(last_out, &at)?;
+                    }
+                    if day_commit.participants[i].check_in(at) {
+                        added.push(p);
+                    }
+                }
+                None => {
+                    added.push(p.clone());
+                    day_commit.participants.push(Participant::new(p, at));
+                }
             }
         }
-        day_commit
+        Ok(day_commit)
     })?;
     Ok(added)
 }
 
-fn rm(app: &App, participants: Vec<String>) -> Result<()> {
-    app.edit_working_commit(|mut day_commit| {
+fn rm(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    participants: Vec<String>,
+) -> Result<()> {
+    app.edit_working_commit(session, branch, |mut day_commit| {
         for p in participants {
             day_commit.participants.retain(|dp| dp.name != p);
         }
-        day_commit
+        Ok(day_commit)
     }).map(|_| ())
 }
 
-fn status(app: &App) -> Result<DayCommit> {
-    app.get_working_commit()
+/// Times are checked to be between their last check-in and now.
+fn out(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    participants: Vec<(String, Option<Time>)>,
+) -> Result<Vec<String>> {
+    let now = app.now(session)?;
+    let mut checked_out: Vec<String> = vec![];
+
+    app.edit_working_commit(session, branch, |mut day_commit| {
+        for (p, time) in participants {
+            let at = match time {
+                Some(time) => day_commit.at_before(&time, &now)?,
+                None => now,
+            };
+            let i = match day_commit.participants.iter().position(|dp| dp.name == p) {
+                Some(i) => i,
+                None => continue,
+            };
+            let last_in = day_commit.participants[i]
+                .stays()
+                .last()
+                .map(|stay| stay.in_at(&day_commit.date));
+            if let Some(ref last_in) = last_in {
+                day_commit.elapsed(last_in, &at)?;
+            }
+            day_commit.participants[i].resume(at)?;
+            if day_commit.participants[i].check_out(at) {
+                checked_out.push(p);
+            }
+        }
+        Ok(day_commit)
+    })?;
+    Ok(checked_out)
+}
+
+/// `Ok(false)` if the shift is already on a break.
+fn pause_shift(app: &App, session: &Session, branch: Option<&str>, pause: Pause) -> Result<bool> {
+    let now = app.now(session)?;
+    let mut paused = false;
+
+    app.edit_working_commit(session, branch, |mut day_commit| {
+        let at = match pause.at {
+            Some(time) => day_commit.at_before(&time, &now)?,
+            None => now,
+        };
+        paused = Break::start_in(&mut day_commit.breaks, at, pause.reason)?;
+        Ok(day_commit)
+    })?;
+    Ok(paused)
+}
+
+/// Only participants who are in and not on a break yet take one.
+fn pause_participants(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    pause: Pause,
+) -> Result<Vec<String>> {
+    let now = app.now(session)?;
+    let mut paused: Vec<String> = vec![];
+
+    app.edit_working_commit(session, branch, |mut day_commit| {
+        let at = match pause.at {
+            Some(time) => day_commit.at_before(&time, &now)?,
+            None => now,
+        };
+        for name in pause.names {
+            let i = match day_commit.participants.iter().position(|p| p.name == name) {
+                Some(i) => i,
+                None => continue,
+            };
+            let last_in = day_commit.participants[i]
+                .stays()
+                .last()
+                .map(|stay| stay.in_at(&day_commit.date));
+            if let Some(ref last_in) = last_in {
+                day_commit.elapsed(last_in, &at)?;
+            }
+            if day_commit.participants[i].pause(at, pause.reason.clone())? {
+                paused.push(name);
+            }
+        }
+        Ok(day_commit)
+    })?;
+    Ok(paused)
 }
 
-fn commit(app: &App, message: String) -> Result<DayCommit> {
-    use chrono::prelude::*;
-    app.commit_a_day(Local::now().time().into(), message)
+/// `Ok(false)` if the shift is not on a break.
+fn resume_shift(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    at: Option<Time>,
+) -> Result<bool> {
+    let now = app.now(session)?;
+    let mut resumed = false;
+
+    app.edit_working_commit(session, branch, |mut day_commit| {
+        let at = match at {
+            Some(time) => day_commit.at_before(&time, &now)?,
+            None => now,
+        };
+        resumed = Break::end_in(&mut day_commit.breaks, at)?;
+        Ok(day_commit)
+    })?;
+    Ok(resumed)
 }
 
-fn reset(app: &App) -> Result<()> {
-    app.remove_working_commit()
+fn resume_participants(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    names: Vec<String>,
+    at: Option<Time>,
+) -> Result<Vec<String>> {
+    let now = app.now(session)?;
+    let mut resumed: Vec<String> = vec![];
+
+    app.edit_working_commit(session, branch, |mut day_commit| {
+        let at = match at {
+            Some(time) => day_commit.at_before(&time, &now)?,
+            None => now,
+        };
+        for name in names {
+            let p = match day_commit.participants.iter_mut().find(|p| p.name == name) {
+                Some(p) => p,
+                None => continue,
+            };
+            if p.resume(at)? {
+                resumed.push(name);
+            }
+        }
+        Ok(day_commit)
+    })?;
+    Ok(resumed)
 }
 
-fn log(app: &App) -> Result<Vec<DayCommit>> {
-    let commits = app.get_working_directory_commit()?;
+/// Only the shift on `branch` if one is named, every running shift otherwise.
+/// Fails with `NotInitialized` if there is none.
+fn status(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+) -> Result<Vec<(Option<String>, DayCommit)>> {
+    if branch.is_some() {
+        let day_commit = app.get_working_commit(session, branch)?;
+        return Ok(vec![(branch.map(|b| b.to_owned()), day_commit)]);
+    }
+    let branches = app.get_working_commits(session)?;
+    if branches.is_empty() {
+        bail!(ErrorKind::NotInitialized);
+    }
+    Ok(branches)
+}
+
+fn commit(
+    app: &App,
+    session: &Session,
+    branch: Option<&str>,
+    end_time: Option<Time>,
+    message: String,
+) -> Result<DayCommit> {
+    app.commit_a_day(session, branch, end_time, message)
+}
+
+fn amend_commit(app: &App, session: &Session, amend: Amend) -> Result<DayCommit> {
+    app.amend_last_commit(session, |mut day_commit| {
+        if let Some(message) = amend.message {
+            day_commit.message = Some(message);
+        }
+        if let Some(end_time) = amend.end_time {
+            day_commit.set_end_time(&end_time);
+        }
+        for p in amend.add {
+            let pp = Participant::new(p, day_commit.start());
+            if !day_commit.participants.contains(&pp) {
+                day_commit.participants.push(pp);
+            }
+        }
+        for p in amend.rm {
+            day_commit.participants.retain(|dp| dp.name != p);
+        }
+        day_commit
+    })
+}
+
+fn edit_commit(
+    app: &App,
+    session: &Session,
+    key: &DayKey,
+    edit: Edit,
+) -> Result<(DayCommit, DayCommit)> {
+    app.edit_commit(session, key, |mut day_commit| {
+        match edit {
+            Edit::Set {
+                start_time,
+                end_time,
+                message,
+            } => {
+                if let Some(start_time) = start_time {
+                    day_commit.start_time = start_time;
+                }
+                if let Some(end_time) = end_time {
+                    day_commit.set_end_time(&end_time);
+                }
+                if let Some(message) = message {
+                    day_commit.message = Some(message);
+                }
+            }
+            Edit::Add(names) => for name in names {
+                let pp = Participant::new(name, day_commit.start());
+                if !day_commit.participants.contains(&pp) {
+                    day_commit.participants.push(pp);
+                }
+            },
+            Edit::Remove(names) => for name in names {
+                day_commit.participants.retain(|dp| dp.name != name);
+            },
+        }
+        day_commit
+    })
+}
+
+fn drop_commit(app: &App, session: &Session, key: &DayKey) -> Result<DayCommit> {
+    app.drop_commit(session, key)
+}
+
+fn stash(app: &App, session: &Session, branch: Option<&str>) -> Result<DayCommit> {
+    app.stash_working_commit(session, branch)
+}
+
+fn stash_list(app: &App, session: &Session) -> Result<Vec<DayCommit>> {
+    app.get_stashed_commits(session)
+}
+
+fn pop_stash(app: &App, session: &Session, branch: Option<&str>, n: usize) -> Result<DayCommit> {
+    app.pop_stashed_commit(session, branch, n)
+}
+
+fn reset(app: &App, session: &Session, branch: Option<&str>) -> Result<()> {
+    app.remove_working_commit(session, branch)
+}
+
+fn log(app: &App, session: &Session) -> Result<Vec<DayCommit>> {
+    let commits = app.get_working_directory_commit(session)?;
     if commits.is_empty() {
         bail!(ErrorKind::NotInitialized);
     }
     Ok(commits)
 }
 
-fn push(app: &App) -> Result<()> {
-    app.push_a_month()
+fn push(app: &App, session: &Session) -> Result<Vec<(i32, u32)>> {
+    app.push_a_month(session)
+}
+
+fn push_preview(app: &App, session: &Session) -> Result<PushPreview> {
+    app.push_preview(session)
+}
+
+fn archived_log(app: &App, session: &Session, year: i32, month: u32) -> Result<Vec<DayCommit>> {
+    app.get_archived_month_commit(session, year, month)
+}
+
+fn year_log(app: &App, session: &Session, year: i32) -> Result<Vec<MonthCommits>> {
+    let mut months = vec![];
+    for (y, month) in app.get_archived_months(session)? {
+        if y == year {
+            months.push(((y, month), app.get_archived_month_commit(session, y, month)?));
+        }
+    }
+    Ok(months)
+}
+
+fn unpush(app: &App, session: &Session, year: i32, month: u32) -> Result<()> {
+    app.unpush_a_month(session, year, month)
+}
+
+fn months(app: &App, session: &Session) -> Result<Vec<(i32, u32)>> {
+    app.get_archived_months(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, NaiveDate};
+    use slack::{Block, Text, MAX_BLOCKS, SECTION_TEXT_MAX};
+    use store::MemoryStore;
+
+    fn app() -> App {
+        let settings = serde_json::from_str(
+            r#"{"api_token": "", "data_path": "/tmp/", "timezone": "UTC"}"#,
+        ).unwrap();
+        App::with_store(settings, Box::new(MemoryStore::new()))
+    }
+
+    fn session() -> Session {
+        Session {
+            team_id: "T1".to_owned(),
+            channel_id: "C1".to_owned(),
+        }
+    }
+
+    fn payload(action_id: &str) -> Payload {
+        serde_json::from_str(&format!(
+            r#"{{"type": "block_actions", "user": {{"id": "U1", "username": "minsu"}},
+                "team": {{"id": "T1"}}, "channel": {{"id": "C1"}},
+                "response_url": "http://localhost/", "actions": [{{"action_id": "{}"}}]}}"#,
+            action_id
+        )).unwrap()
+    }
+
+    fn start(app: &App) {
+        let start = Start {
+            date: None,
+            time: None,
+        };
+        init(app, &session(), None, start).unwrap();
+    }
+
+    /// The buttons of the first shift in a status message
+    fn buttons(value: &serde_json::Value) -> Vec<serde_json::Value> {
+        let blocks = value["blocks"].as_array().unwrap();
+        let actions = blocks.iter().find(|b| b["type"] == "actions").unwrap();
+        actions["elements"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn action_join() {
+        let app = app();
+        start(&app);
+        let value = handle_action_with(&app, &payload("join")).unwrap();
+        assert_eq!(value["replace_original"], true);
+        assert_eq!(value["response_type"], "in_channel");
+        let buttons = buttons(&value);
+        assert_eq!(buttons[0]["action_id"], "join");
+        assert!(buttons[0]["confirm"].is_null());
+        assert_eq!(buttons[2]["action_id"], "commit");
+        assert_eq!(buttons[2]["confirm"]["title"]["text"], "근무 끝");
+        let (_, day_commit) = status(&app, &session(), None).unwrap().remove(0);
+        assert_eq!(day_commit.participants[0].name, "minsu");
+        assert!(day_commit.participants[0].stays()[0].out_time.is_none());
+    }
+
+    #[test]
+    fn action_leave() {
+        let app = app();
+        start(&app);
+        handle_action_with(&app, &payload("join")).unwrap();
+        let value = handle_action_with(&app, &payload("leave")).unwrap();
+        assert_eq!(value["replace_original"], true);
+        let (_, day_commit) = status(&app, &session(), None).unwrap().remove(0);
+        assert!(day_commit.participants[0].stays()[0].out_time.is_some());
+    }
+
+    #[test]
+    fn action_commit() {
+        let app = app();
+        start(&app);
+        handle_action_with(&app, &payload("join")).unwrap();
+        let value = handle_action_with(&app, &payload("commit")).unwrap();
+        assert_eq!(value["replace_original"], true);
+        assert_eq!(value["text"], "오늘의 근무가 끝났습니다. 수고하셨습니다!");
+        match status(&app, &session(), None) {
+            Err(Error(ErrorKind::NotInitialized, _)) => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn action_without_shift() {
+        let app = app();
+        let value = handle_action_with(&app, &payload("join")).unwrap();
+        assert_eq!(value["replace_original"], false);
+        assert_eq!(value["response_type"], "ephemeral");
+    }
+
+    #[test]
+    fn action_unknown() {
+        let app = app();
+        start(&app);
+        let value = handle_action_with(&app, &payload("dance")).unwrap();
+        assert_eq!(value["replace_original"], false);
+        assert_eq!(value["response_type"], "ephemeral");
+        let (_, day_commit) = status(&app, &session(), None).unwrap().remove(0);
+        assert!(day_commit.participants.is_empty());
+    }
+
+    #[test]
+    fn edit_end_next_day() {
+        let app = app();
+        let yesterday = app.now(&session()).unwrap().date() - chrono::Duration::days(1);
+        let start = Start {
+            date: Some(yesterday.into()),
+            time: Some(Time(22, 0)),
+        };
+        init(&app, &session(), None, start).unwrap();
+        commit(&app, &session(), None, Some(Time(23, 0)), "test".to_owned()).unwrap();
+        let key = DayKey {
+            day: yesterday.day(),
+            n: 0,
+        };
+        let edit = |end: &str| Edit::Set {
+            start_time: None,
+            end_time: Some(end.parse().unwrap()),
+            message: None,
+        };
+
+        match edit_commit(&app, &session(), &key, edit("6:00")) {
+            Err(Error(ErrorKind::NegativeDuration(..), _)) => {}
+            _ => panic!(),
+        }
+        let (_, after) = edit_commit(&app, &session(), &key, edit("6:00+1")).unwrap();
+        let next_day = yesterday + chrono::Duration::days(1);
+        assert_eq!(after.end(), Some(next_day.and_hms(6, 0, 0)));
+        assert_eq!(after.duration().unwrap(), Some(TimeDiff(8 * 60)));
+    }
+
+    fn request(text: &str) -> Request {
+        Request {
+            token: String::new(),
+            team_id: "T1".to_owned(),
+            team_domain: String::new(),
+            channel_id: "C1".to_owned(),
+            channel_name: String::new(),
+            user_id: "U1".to_owned(),
+            user_name: "minsu".to_owned(),
+            text: text.to_owned(),
+            response_url: String::new(),
+            trigger_id: String::new(),
+        }
+    }
+
+    /// Starts a shift at 10:00 yesterday, so any time after it has come.
+    fn start_yesterday(app: &App, branch: Option<&str>) -> NaiveDate {
+        let yesterday = app.now(&session()).unwrap().date() - chrono::Duration::days(1);
+        let start = Start {
+            date: Some(yesterday.into()),
+            time: Some(Time(10, 0)),
+        };
+        init(app, &session(), branch, start).unwrap();
+        yesterday
+    }
+
+    fn pause_at(hour: u32, names: &[&str]) -> Pause {
+        Pause {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            at: Some(Time(hour, 0)),
+            reason: Some("점심".to_owned()),
+        }
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let app = app();
+        let date = start_yesterday(&app, None);
+        let session = session();
+        add(&app, &session, None, vec![("a".to_owned(), Some(Time(10, 0)))]).unwrap();
+
+        assert!(pause_shift(&app, &session, None, pause_at(12, &[])).unwrap());
+        assert!(!pause_shift(&app, &session, None, pause_at(12, &[])).unwrap());
+        assert!(resume_shift(&app, &session, None, Some(Time(13, 0))).unwrap());
+        assert!(!resume_shift(&app, &session, None, Some(Time(13, 0))).unwrap());
+        let paused = pause_participants(&app, &session, None, pause_at(12, &["a", "b"]));
+        assert_eq!(paused.unwrap(), ["a"]);
+        let names = vec!["a".to_owned()];
+        let resumed = resume_participants(&app, &session, None, names, Some(Time(14, 0)));
+        assert_eq!(resumed.unwrap(), ["a"]);
+        // still running when the shift ends
+        assert!(pause_shift(&app, &session, None, pause_at(17, &[])).unwrap());
+
+        let day_commit = commit(&app, &session, None, Some(Time(18, 0)), "test".to_owned())
+            .unwrap();
+        let end = date.and_hms(18, 0, 0);
+        assert_eq!(day_commit.breaks[1].end(), Some(end));
+        assert_eq!(day_commit.breaks[0].reason, Some("점심".to_owned()));
+        // 10:00 ~ 18:00 without 12:00 ~ 13:00 and 17:00 ~ 18:00
+        assert_eq!(day_commit.duration().unwrap(), Some(TimeDiff(8 * 60 - 60 - 60)));
+        // and without 12:00 ~ 14:00 for a, taking their own break into it
+        let worked = day_commit.participants[0].worked(&day_commit, &end);
+        assert_eq!(worked.unwrap(), TimeDiff(8 * 60 - 120 - 60));
+    }
+
+    #[test]
+    fn stash_pops_back_to_its_branch() {
+        let app = app();
+        start_yesterday(&app, Some("evening"));
+        handle_command_with(&app, request("stash --branch evening")).unwrap();
+        let stashes = stash_list(&app, &session()).unwrap();
+        assert_eq!(stashes[0].stashed_branch, Some("evening".to_owned()));
+        assert_eq!(stashes[0].breaks[0].reason, Some("보관".to_owned()));
+        assert!(stashes[0].breaks[0].end().is_none());
+        assert!(app.get_working_commits(&session()).unwrap().is_empty());
+
+        handle_command_with(&app, request("stash pop")).unwrap();
+        assert!(stash_list(&app, &session()).unwrap().is_empty());
+        let day_commit = app.get_working_commit(&session(), Some("evening")).unwrap();
+        assert!(day_commit.stashed_branch.is_none() && day_commit.stashed_at.is_none());
+        assert!(day_commit.breaks[0].end().is_some());
+
+        handle_command_with(&app, request("stash --branch evening")).unwrap();
+        handle_command_with(&app, request("stash pop --branch night")).unwrap();
+        let branches: Vec<_> = app.get_working_commits(&session())
+            .unwrap()
+            .into_iter()
+            .map(|(branch, _)| branch)
+            .collect();
+        assert_eq!(branches, [Some("night".to_owned())]);
+    }
+
+    #[test]
+    fn error_value_has_id() {
+        let value = error_value(ErrorKind::WorkerStopped.into()).unwrap();
+        assert_eq!(value["response_type"], "ephemeral");
+        let text = value["text"].as_str().unwrap();
+        assert!(text.starts_with("지금은 요청을 처리할 수 없습니다."));
+        assert!(text.contains("오류 ID `"));
+    }
+
+    #[test]
+    fn record_blocks_cut_long_text() {
+        let long = "a".repeat(SECTION_TEXT_MAX * 2);
+        let fields = vec![("근무 내용".to_owned(), long.clone())];
+        let blocks = record_blocks("pretext", "title", &long, &fields);
+        assert_eq!(blocks.len(), 3);
+        match blocks[1] {
+            Block::Section {
+                text: Some(Text::Mrkdwn { ref text }),
+                ..
+            } => assert_eq!(text.chars().count(), SECTION_TEXT_MAX),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn block_message_caps_blocks() {
+        let blocks = (0..MAX_BLOCKS + 10).map(|_| Block::Divider).collect();
+        match block_message(slack::ResponseType::InChannel, "text", blocks) {
+            Response::BlockMessage(m) => {
+                assert_eq!(m.blocks.len(), MAX_BLOCKS);
+                match m.blocks[MAX_BLOCKS - 1] {
+                    Block::Context { ref elements } => match elements[0] {
+                        Text::Mrkdwn { ref text } => assert!(text.contains("11개")),
+                        _ => panic!(),
+                    },
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
 }
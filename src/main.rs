@@ -5,22 +5,102 @@ extern crate rocket;
 extern crate rocket_contrib;
 extern crate serde_json;
 
-use rocket::request::LenientForm;
+use std::io::Read;
+
+use rocket::http::Status;
+use rocket::request::{self, FormItems, FromForm, FromRequest};
+use rocket::response::Failure;
+use rocket::{Data, Outcome};
+use glt::slack::interaction;
+use glt::slack::signing::Signature;
 use glt::slack::slash_command::Request;
-use glt::{handle_command, Result};
+use glt::{handle_action, handle_command, Error, ErrorKind, Result};
+
+/// Slash command and interaction bodies are a few kilobytes at most, anything
+/// this long isn't one.
+const BODY_LIMIT: u64 = 1 << 16;
 
 fn main() {
     rocket::ignite()
         .mount("/request", routes![command_request])
+        .mount("/interactive", routes![interactive_request])
         .mount("/ping", routes![ping])
         .launch();
 }
 
-#[post("/", data = "<form>")]
-fn command_request(form: LenientForm<Request>) -> Result<rocket_contrib::Json> {
-    let data = form.into_inner();
-    let json = handle_command(data)?;
-    Ok(rocket_contrib::Json(json))
+/// Slack's signature headers, if the request has them
+struct SlackSignature(Option<Signature>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for SlackSignature {
+    type Error = ();
+
+    fn from_request(request: &'a rocket::Request<'r>) -> request::Outcome<SlackSignature, ()> {
+        let headers = request.headers();
+        Outcome::Success(SlackSignature(
+            match (
+                headers.get_one("X-Slack-Request-Timestamp"),
+                headers.get_one("X-Slack-Signature"),
+            ) {
+                (Some(timestamp), Some(signature)) => Some(Signature {
+                    timestamp: timestamp.to_owned(),
+                    signature: signature.to_owned(),
+                }),
+                _ => None,
+            },
+        ))
+    }
+}
+
+/// Unverified requests get `401`, other errors are logged and get `500`.
+#[post("/", data = "<data>")]
+fn command_request(
+    signature: SlackSignature,
+    data: Data,
+) -> ::std::result::Result<Result<rocket_contrib::Json>, Failure> {
+    rejected(read_command(signature.0.as_ref(), data)).map(|r| r.map(rocket_contrib::Json))
+}
+
+/// Button clicks. The message clicked on is updated later through its
+/// `response_url`, so a verified request gets an empty `200` right away.
+#[post("/", data = "<data>")]
+fn interactive_request(
+    signature: SlackSignature,
+    data: Data,
+) -> ::std::result::Result<Result<()>, Failure> {
+    rejected(read_action(signature.0.as_ref(), data))
+}
+
+/// Unverified requests as `401` and malformed ones as `400`.
+fn rejected<T>(result: Result<T>) -> ::std::result::Result<Result<T>, Failure> {
+    match result {
+        Err(Error(ErrorKind::InvalidToken, _))
+        | Err(Error(ErrorKind::InvalidSignature, _))
+        | Err(Error(ErrorKind::StaleRequest(_), _)) => Err(Failure(Status::Unauthorized)),
+        Err(Error(ErrorKind::InvalidSubmission, _)) => Err(Failure(Status::BadRequest)),
+        r => Ok(r),
+    }
+}
+
+/// The signature covers the raw body, so the form is parsed by hand after
+/// reading it.
+fn read_command(signature: Option<&Signature>, data: Data) -> Result<serde_json::Value> {
+    let mut body = String::new();
+    data.open().take(BODY_LIMIT).read_to_string(&mut body)?;
+    let form = match Request::from_form(&mut FormItems::from(body.as_str()), false) {
+        Ok(form) => form,
+        Err(_) => return Err(ErrorKind::InvalidSubmission.into()),
+    };
+    handle_command(signature, &body, form)
+}
+
+fn read_action(signature: Option<&Signature>, data: Data) -> Result<()> {
+    let mut body = String::new();
+    data.open().take(BODY_LIMIT).read_to_string(&mut body)?;
+    let form = match interaction::Form::from_form(&mut FormItems::from(body.as_str()), false) {
+        Ok(form) => form,
+        Err(_) => return Err(ErrorKind::InvalidSubmission.into()),
+    };
+    handle_action(signature, &body, &form)
 }
 
 #[post("/")]
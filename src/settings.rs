@@ -1,8 +1,95 @@
+use app::Session;
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+/// `response_retries` is capped at this so a failing `response_url` can't
+/// hold the worker for hours.
+pub const MAX_RESPONSE_RETRIES: u32 = 10;
+
 #[derive(Deserialize)]
 pub struct Settings {
-    pub verification_token: String,
+    /// Signing secret of the Slack app, to verify requests by their signature
+    pub signing_secret: Option<String>,
+    /// Deprecated token sent in every request, only checked when
+    /// `accept_verification_token` is set and a request isn't signed
+    pub verification_token: Option<String>,
+    #[serde(default)]
+    pub accept_verification_token: bool,
     pub api_token: String,
     pub data_path: String,
+    #[serde(default = "default_storage")]
+    pub storage: String,
+    pub sqlite_path: Option<String>,
+    pub git_remote: Option<String>,
+    pub default_team_id: Option<String>,
+    pub default_channel_id: Option<String>,
+    /// Slack user IDs allowed to run admin commands
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// Slack user ID to the name they are recorded under when they click a
+    /// button, their Slack username if missing. Keys are lowercased.
+    #[serde(default)]
+    pub user_names: HashMap<String, String>,
+    /// IANA time zone name, like `Asia/Seoul`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// `<team_id>/<channel_id>` to a time zone overriding `timezone`. A bare
+    /// channel ID applies to that channel in any team. Keys are lowercased.
+    #[serde(default)]
+    pub channel_timezones: HashMap<String, String>,
+    /// How many more times a delayed response is posted after it fails, at
+    /// most `MAX_RESPONSE_RETRIES`
+    #[serde(default = "default_response_retries")]
+    pub response_retries: u32,
+    #[serde(default = "default_message_format")]
+    pub message_format: MessageFormat,
+}
+
+/// How `status`, `commit` and `log` lay out their messages
+#[derive(Clone, Copy)]
+pub enum MessageFormat {
+    Blocks,
+    /// Legacy attachments, which Slack has deprecated
+    Attachments,
+}
+
+/// By hand from a string, as config can't deserialize enums.
+impl<'de> Deserialize<'de> for MessageFormat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MessageFormat, D::Error> {
+        use serde::de::Error;
+        match String::deserialize(deserializer)?.as_str() {
+            "blocks" => Ok(MessageFormat::Blocks),
+            "attachments" => Ok(MessageFormat::Attachments),
+            other => Err(D::Error::unknown_variant(other, &["blocks", "attachments"])),
+        }
+    }
+}
+
+fn default_storage() -> String {
+    "json".to_owned()
+}
+
+fn default_timezone() -> String {
+    "Asia/Seoul".to_owned()
+}
+
+fn default_response_retries() -> u32 {
+    3
+}
+
+fn default_message_format() -> MessageFormat {
+    MessageFormat::Blocks
+}
+
+/// `None` for an empty value, like `signing_secret = ""` left from the
+/// example settings, which no request could be verified with.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.and_then(|v| if v.is_empty() { None } else { Some(v) })
+}
+
+fn lowercase_keys<V>(map: HashMap<String, V>) -> HashMap<String, V> {
+    map.into_iter().map(|(k, v)| (k.to_lowercase(), v)).collect()
 }
 
 impl Settings {
@@ -16,7 +103,18 @@ impl Settings {
         } else {
             File::with_name("settings")
         })?;
-        settings.try_into::<Settings>()
+        Settings::from_config(settings)
+    }
+
+    fn from_config(config: ::config::Config) -> Result<Settings, ::config::ConfigError> {
+        let mut settings = config.try_into::<Settings>()?;
+        settings.response_retries = settings.response_retries.min(MAX_RESPONSE_RETRIES);
+        settings.signing_secret = non_empty(settings.signing_secret);
+        settings.verification_token = non_empty(settings.verification_token);
+        // config lowercases table keys, so IDs are looked up lowercased
+        settings.channel_timezones = lowercase_keys(settings.channel_timezones);
+        settings.user_names = lowercase_keys(settings.user_names);
+        Ok(settings)
     }
 
     pub fn assure_new() -> Settings {
@@ -33,4 +131,122 @@ impl Settings {
         }
         s
     }
+
+    pub fn user_name(&self, user_id: &str) -> Option<&str> {
+        self.user_names
+            .get(&user_id.to_lowercase())
+            .map(|name| name.as_str())
+    }
+
+    /// The session that receives data recorded before sessions existed.
+    pub fn default_session(&self) -> Session {
+        Session {
+            team_id: self.default_team_id
+                .clone()
+                .unwrap_or_else(|| "default".to_owned()),
+            channel_id: self.default_channel_id
+                .clone()
+                .unwrap_or_else(|| "default".to_owned()),
+        }
+    }
+
+    pub fn timezone_name(&self, session: &Session) -> &str {
+        let key = format!("{}/{}", session.team_id, session.channel_id).to_lowercase();
+        self.channel_timezones
+            .get(&key)
+            .or_else(|| {
+                self.channel_timezones
+                    .get(&session.channel_id.to_lowercase())
+            })
+            .unwrap_or(&self.timezone)
+    }
+
+    pub fn timezone(&self, session: &Session) -> ::error::Result<Tz> {
+        let name = self.timezone_name(session);
+        name.parse()
+            .map_err(|_| ::ErrorKind::InvalidSetting("timezone", name.to_owned()).into())
+    }
+
+    /// Fails on the first time zone name that can't be parsed.
+    pub fn check_timezones(&self) -> ::error::Result<()> {
+        for name in Some(&self.timezone)
+            .into_iter()
+            .chain(self.channel_timezones.values())
+        {
+            if name.parse::<Tz>().is_err() {
+                bail!(::ErrorKind::InvalidSetting("timezone", name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sqlite_path(&self) -> String {
+        match self.sqlite_path {
+            Some(ref path) => path.clone(),
+            None => self.data_path.clone() + "glt.sqlite3",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Config, File, FileFormat};
+
+    fn load(toml: &str) -> Result<Settings, ::config::ConfigError> {
+        let mut config = Config::new();
+        config.merge(File::from_str(toml, FileFormat::Toml)).unwrap();
+        Settings::from_config(config)
+    }
+
+    /// `settings.default.toml` with its commented examples filled in
+    fn example_settings() -> Settings {
+        let toml = include_str!("../settings.default.toml")
+            .replace("# [channel_timezones]\n# ", "[channel_timezones]\n")
+            .replace("# [user_names]\n# ", "[user_names]\n");
+        load(&toml).unwrap()
+    }
+
+    fn session(team_id: &str, channel_id: &str) -> Session {
+        Session {
+            team_id: team_id.to_owned(),
+            channel_id: channel_id.to_owned(),
+        }
+    }
+
+    #[test]
+    fn message_format_from_config() {
+        let toml = "api_token = \"\"\ndata_path = \"\"\nmessage_format = ";
+        match load(&(toml.to_owned() + "\"attachments\"")).unwrap().message_format {
+            MessageFormat::Attachments => {}
+            MessageFormat::Blocks => panic!(),
+        }
+        assert!(load(&(toml.to_owned() + "\"fancy\"")).is_err());
+    }
+
+    #[test]
+    fn empty_secrets_unset() {
+        assert!(example_settings().signing_secret.is_none());
+        let toml = "api_token = \"\"\ndata_path = \"\"\n";
+        let settings = load(&format!("{}signing_secret = \"\"\nverification_token = \"\"", toml))
+            .unwrap();
+        assert!(settings.signing_secret.is_none() && settings.verification_token.is_none());
+        let settings = load(&format!("{}signing_secret = \"s\"", toml)).unwrap();
+        assert_eq!(settings.signing_secret, Some("s".to_owned()));
+    }
+
+    #[test]
+    fn channel_timezone_from_example() {
+        let settings = example_settings();
+        assert_eq!(settings.timezone_name(&session("T0123456789", "C0123456789")), "UTC");
+        assert_eq!(settings.timezone_name(&session("T0123456789", "C9")), "Asia/Seoul");
+        assert_eq!(settings.timezone_name(&session("T9", "C0123456789")), "Asia/Seoul");
+    }
+
+    #[test]
+    fn user_name_from_example() {
+        let settings = example_settings();
+        assert_eq!(settings.user_name("U0123456789"), Some("민수"));
+        assert_eq!(settings.user_name("U9"), None);
+    }
 }
@@ -0,0 +1,48 @@
+/// What Slack posts when someone clicks a button, the JSON being in `payload`
+#[derive(FromForm)]
+pub struct Form {
+    pub payload: String,
+}
+
+/// A `block_actions` payload, with only what glt reads
+#[derive(Deserialize)]
+pub struct Payload {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub token: String,
+    pub user: ActionUser,
+    pub team: Id,
+    pub channel: Id,
+    #[serde(default)]
+    pub container: Container,
+    pub response_url: String,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+#[derive(Deserialize)]
+pub struct ActionUser {
+    pub id: String,
+    #[serde(default)]
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+pub struct Id {
+    pub id: String,
+}
+
+/// Where the clicked button is
+#[derive(Deserialize, Default)]
+pub struct Container {
+    #[serde(default)]
+    pub is_ephemeral: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Action {
+    pub action_id: String,
+    #[serde(default)]
+    pub value: Option<String>,
+}
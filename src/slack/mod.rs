@@ -1,3 +1,5 @@
+pub mod interaction;
+pub mod signing;
 pub mod slash_command;
 
 #[derive(Serialize, Deserialize)]
@@ -23,6 +25,7 @@ pub struct Team {
 pub enum Response {
     Message(Message),
     AttachedMessage(AttachedMessage),
+    BlockMessage(BlockMessage),
 }
 
 #[derive(Serialize)]
@@ -58,3 +61,169 @@ pub struct AttachmentFields {
     pub title: String,
     pub value: String,
 }
+
+/// A message laid out with Block Kit
+#[derive(Serialize)]
+pub struct BlockMessage {
+    pub response_type: ResponseType,
+    /// Shown in notifications and wherever blocks can't be
+    pub text: String,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Section {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<Text>,
+        /// Two columns of at most `SECTION_FIELDS`
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<Text>,
+    },
+    /// Small grey text
+    Context { elements: Vec<Text> },
+    Divider,
+    Actions { elements: Vec<Element> },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Text {
+    Mrkdwn { text: String },
+    PlainText { text: String },
+}
+
+impl Text {
+    pub fn mrkdwn<S: Into<String>>(text: S) -> Text {
+        Text::Mrkdwn { text: text.into() }
+    }
+
+    pub fn plain<S: Into<String>>(text: S) -> Text {
+        Text::PlainText { text: text.into() }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Element {
+    Button {
+        text: Text,
+        action_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        style: Option<ButtonStyle>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confirm: Option<Confirm>,
+    },
+}
+
+/// A dialog Slack shows before the button does anything
+#[derive(Serialize)]
+pub struct Confirm {
+    pub title: Text,
+    pub text: Text,
+    pub confirm: Text,
+    pub deny: Text,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonStyle {
+    Primary,
+    Danger,
+}
+
+/// Slack puts no more fields than this in a section.
+pub const SECTION_FIELDS: usize = 10;
+/// Characters Slack takes in the text of a section
+pub const SECTION_TEXT_MAX: usize = 3000;
+/// Characters Slack takes in a field of a section
+pub const FIELD_TEXT_MAX: usize = 2000;
+/// Blocks Slack takes in a message
+pub const MAX_BLOCKS: usize = 50;
+
+/// Escapes the characters Slack reads as markup in text objects.
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `s` cut to `max` characters, the last of them `…` when it is cut.
+pub fn truncate(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        None => s.to_owned(),
+        Some(_) => {
+            let end = s.char_indices().nth(max - 1).unwrap().0;
+            s[..end].to_owned() + "…"
+        }
+    }
+}
+
+impl Block {
+    /// A section of mrkdwn `text`, cut to `SECTION_TEXT_MAX`
+    pub fn text(text: &str) -> Block {
+        Block::Section {
+            text: Some(Text::mrkdwn(truncate(text, SECTION_TEXT_MAX))),
+            fields: vec![],
+        }
+    }
+
+    /// Sections of mrkdwn `fields`, `SECTION_FIELDS` in each, every field cut
+    /// to `FIELD_TEXT_MAX`
+    pub fn fields(fields: Vec<String>) -> Vec<Block> {
+        let mut fields: Vec<Text> = fields
+            .iter()
+            .map(|f| Text::mrkdwn(truncate(f, FIELD_TEXT_MAX)))
+            .collect();
+        let mut blocks = vec![];
+        while !fields.is_empty() {
+            let at = ::std::cmp::min(SECTION_FIELDS, fields.len());
+            let rest = fields.split_off(at);
+            blocks.push(Block::Section { text: None, fields });
+            fields = rest;
+        }
+        blocks
+    }
+
+    /// A context of mrkdwn `text`, cut to `SECTION_TEXT_MAX`
+    pub fn context(text: &str) -> Block {
+        Block::Context {
+            elements: vec![Text::mrkdwn(truncate(text, SECTION_TEXT_MAX))],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_chars() {
+        assert_eq!(truncate("근무 기록", 5), "근무 기록");
+        assert_eq!(truncate("근무 기록", 4), "근무 …");
+        assert_eq!(truncate("", 1), "");
+    }
+
+    #[test]
+    fn fields_split_and_cut() {
+        let long = "가".repeat(FIELD_TEXT_MAX + 1);
+        let blocks = Block::fields(vec![long; SECTION_FIELDS + 1]);
+        assert_eq!(blocks.len(), 2);
+        match blocks[0] {
+            Block::Section { ref fields, .. } => {
+                assert_eq!(fields.len(), SECTION_FIELDS);
+                match fields[0] {
+                    Text::Mrkdwn { ref text } => {
+                        assert_eq!(text.chars().count(), FIELD_TEXT_MAX);
+                        assert!(text.ends_with('…'));
+                    }
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+}
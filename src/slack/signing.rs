@@ -0,0 +1,98 @@
+use error::{ErrorKind, Result};
+use ring::{constant_time, digest, hmac};
+
+/// Requests signed more than this many seconds away from now are rejected, so
+/// a captured request can't be replayed later.
+pub const MAX_AGE: i64 = 60 * 5;
+
+/// `X-Slack-Request-Timestamp` and `X-Slack-Signature` of a request
+pub struct Signature {
+    pub timestamp: String,
+    pub signature: String,
+}
+
+impl Signature {
+    /// Checks the v0 signature over the raw `body` with the signing `secret`,
+    /// as of `now` in Unix seconds.
+    pub fn verify(&self, secret: &str, body: &str, now: i64) -> Result<()> {
+        let timestamp: i64 = match self.timestamp.parse() {
+            Ok(timestamp) => timestamp,
+            Err(_) => bail!(ErrorKind::InvalidSignature),
+        };
+        // A timestamp so far off that the difference overflows is stale too
+        if now.checked_sub(timestamp).map_or(true, |d| d.abs() > MAX_AGE) {
+            bail!(ErrorKind::StaleRequest(timestamp));
+        }
+        let expected = sign(secret, &self.timestamp, body);
+        constant_time::verify_slices_are_equal(expected.as_bytes(), self.signature.as_bytes())
+            .map_err(|_| ErrorKind::InvalidSignature.into())
+    }
+}
+
+/// `v0=<hex>`, the HMAC-SHA256 of `v0:<timestamp>:<body>`
+pub fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let key = hmac::SigningKey::new(&digest::SHA256, secret.as_bytes());
+    let base = format!("v0:{}:{}", timestamp, body);
+    let hex: Vec<String> = hmac::sign(&key, base.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("v0={}", hex.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+
+    // The example from https://api.slack.com/authentication/verifying-requests-from-slack
+    const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+    const TIMESTAMP: &str = "1531420618";
+    const BODY: &str = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow\
+                        &channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA\
+                        &user_name=roadrunner&command=%2Fwebhook-collect&text=\
+                        &response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J\
+                        %2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN\
+                        &trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+    const SIGNATURE: &str = "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503";
+
+    fn signature(timestamp: &str, signature: &str) -> Signature {
+        Signature {
+            timestamp: timestamp.to_owned(),
+            signature: signature.to_owned(),
+        }
+    }
+
+    #[test]
+    fn sign_slack_example() {
+        assert_eq!(sign(SECRET, TIMESTAMP, BODY), SIGNATURE);
+    }
+
+    #[test]
+    fn verify_slack_example() {
+        let now = 1531420618 + MAX_AGE;
+        assert!(signature(TIMESTAMP, SIGNATURE).verify(SECRET, BODY, now).is_ok());
+        match signature(TIMESTAMP, "v0=00").verify(SECRET, BODY, now) {
+            Err(Error(ErrorKind::InvalidSignature, _)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn verify_rejects_stale() {
+        match signature(TIMESTAMP, SIGNATURE).verify(SECRET, BODY, 1531420618 + MAX_AGE + 1) {
+            Err(Error(ErrorKind::StaleRequest(1531420618), _)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn verify_rejects_overflowing_timestamp() {
+        let sig = signature("-9223372036854775808", SIGNATURE);
+        match sig.verify(SECRET, BODY, 1531420618) {
+            Err(Error(ErrorKind::StaleRequest(_), _)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+}
@@ -11,27 +11,3 @@ pub struct Request {
     pub response_url: String,
     pub trigger_id: String,
 }
-
-#[derive(Serialize)]
-pub struct Response {
-    pub response_type: String,
-    pub text: String,
-    pub attachments: ResponseAttachments,
-}
-
-#[derive(Serialize)]
-pub struct ResponseAttachments {
-    pub fallback: String,
-    pub color: String,
-    pub pretext: String,
-    pub title: String,
-    pub text: String,
-    pub fields: Vec<AttachmentField>,
-}
-
-#[derive(Serialize)]
-pub struct AttachmentField {
-    pub title: String,
-    pub value: String,
-    pub short: bool,
-}
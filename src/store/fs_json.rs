@@ -0,0 +1,567 @@
+use app::{DayCommit, DayKey, MonthCommits, Session};
+use error::{ErrorKind, Result, ResultExt};
+use super::{Store, StoreLock};
+use fs2::FileExt;
+use std::fs::{create_dir_all, rename, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde_json;
+
+/// The on-disk layout of a session, under `sessions/<team_id>/<channel_id>/`:
+///
+/// - `working.json`: the working shift of the default branch
+/// - `branches/<branch>.json`: the working shifts of the other branches
+/// - `stash.json`: stashed shifts, the latest first
+/// - `working/<day>[_n].json`: committed days of the current month
+/// - `<year>/<month>/`: archived months
+/// - `.lock`: advisory lock file taken by `Store::lock`
+///
+/// A shift is filed under the day it started, even if it ended on a later one.
+///
+/// Data from before sessions existed lives directly under `data_path` and is
+/// moved into the default session by `migrate_legacy`.
+///
+/// Every file is written to a temporary sibling, fsynced and renamed into
+/// place, so a crash never leaves a truncated file behind. Moving a shift in
+/// or out of the stash touches two files, and the one it moves to is written
+/// first: a crash in between leaves it in both places, never in neither.
+pub struct FsJsonStore {
+    data_path: PathBuf,
+}
+
+impl FsJsonStore {
+    pub fn new<P: Into<PathBuf>>(data_path: P) -> FsJsonStore {
+        FsJsonStore {
+            data_path: data_path.into(),
+        }
+    }
+
+    fn get_commit_from_file(file: &File) -> Result<DayCommit> {
+        serde_json::from_reader(file).map_err(|e| ErrorKind::Json(e).into())
+    }
+
+    /// Fails with `BrokenRecord` and the path if the file can't be read.
+    fn get_commit_from_path(path: PathBuf) -> Result<DayCommit> {
+        File::open(&path)
+            .map_err(|e| e.into())
+            .and_then(|file| FsJsonStore::get_commit_from_file(&file))
+            .chain_err(|| ErrorKind::BrokenRecord(path.display().to_string()))
+    }
+
+    /// `sessions/<team_id>/<channel_id>`, relative to `data_path`.
+    pub fn session_dir(session: &Session) -> Result<PathBuf> {
+        if !is_valid_name(&session.team_id) || !is_valid_name(&session.channel_id) {
+            bail!(ErrorKind::InvalidSubmission);
+        }
+
+        let mut path = PathBuf::from("sessions");
+        path.push(&session.team_id);
+        path.push(&session.channel_id);
+        Ok(path)
+    }
+
+    fn session_path(&self, session: &Session) -> Result<PathBuf> {
+        Ok(self.data_path.join(FsJsonStore::session_dir(session)?))
+    }
+
+    fn working_file_path(&self, session: &Session, branch: Option<&str>) -> Result<PathBuf> {
+        let mut path = self.session_path(session)?;
+        match branch {
+            Some(branch) => {
+                if !is_valid_name(branch) {
+                    bail!(ErrorKind::InvalidSubmission);
+                }
+                path.push("branches");
+                path.push(format!("{}.json", branch));
+            }
+            None => path.push("working.json"),
+        }
+        Ok(path)
+    }
+
+    fn get_working_file(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        option: &mut OpenOptions,
+    ) -> Result<File> {
+        let path = self.working_file_path(session, branch)?;
+
+        if !path.exists() {
+            bail!(ErrorKind::NotInitialized);
+        }
+
+        option.open(&path).map_err(|e| ErrorKind::Io(e).into())
+    }
+
+    fn stash_path(&self, session: &Session) -> Result<PathBuf> {
+        let mut path = self.session_path(session)?;
+        path.push("stash.json");
+        Ok(path)
+    }
+
+    fn write_atomic<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+        let mut tmp_path = path.to_path_buf();
+        let mut tmp_name = path.file_name().unwrap().to_os_string();
+        tmp_name.push(".tmp");
+        tmp_path.set_file_name(tmp_name);
+
+        let tmp_file = File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(&tmp_file, value)?;
+        tmp_file.sync_all()?;
+        rename(&tmp_path, path)?;
+
+        FsJsonStore::sync_parent(path)
+    }
+
+    /// Makes a rename or removal in the parent directory durable.
+    fn sync_parent(path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn get_working_directory_entries(&self, session: &Session) -> Result<Vec<::std::fs::DirEntry>> {
+        let mut path = self.session_path(session)?;
+        path.push("working");
+        if !path.exists() {
+            bail!(ErrorKind::NotInitialized);
+        }
+
+        FsJsonStore::get_day_entries(&path)
+    }
+
+    /// Entries of a month directory, ordered by day and then by the `_n` suffix.
+    fn get_day_entries(path: &Path) -> Result<Vec<::std::fs::DirEntry>> {
+        use std::fs::read_dir;
+
+        let mut entries: Vec<_> = read_dir(path)?
+            .filter_map(|d| d.ok())
+            .filter(|d| FsJsonStore::day_file_key(&d.path()).is_some())
+            .collect();
+        entries.sort_by_key(|d| FsJsonStore::day_file_key(&d.path()));
+        Ok(entries)
+    }
+
+    /// `3.json` is `3`, `3_1.json` is `3_1`.
+    fn day_file_key(path: &Path) -> Option<DayKey> {
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            return None;
+        }
+        path.file_stem()?.to_str()?.parse().ok()
+    }
+
+    fn day_file_path(&self, session: &Session, key: &DayKey) -> Result<PathBuf> {
+        let mut path = self.session_path(session)?;
+        path.push("working");
+        path.push(key.to_string());
+        path.set_extension("json");
+        Ok(path)
+    }
+
+    /// `<day>.json` in `dir`, or `<day>_<n>.json` with the first free `n`.
+    fn free_day_path(mut path: PathBuf, day: u32) -> PathBuf {
+        path.push(day.to_string());
+        path.set_extension("json");
+
+        let mut i: usize = 1;
+        while path.exists() {
+            path.pop();
+            path.push(day.to_string() + "_" + &i.to_string());
+            path.set_extension("json");
+            i += 1;
+        }
+        path
+    }
+
+    fn get_commits_from_entries(dir: Vec<::std::fs::DirEntry>) -> Result<Vec<DayCommit>> {
+        dir.into_iter()
+            .map(|f| FsJsonStore::get_commit_from_path(f.path()))
+            .collect()
+    }
+
+    fn child_dirs<T: ::std::str::FromStr + Ord>(path: &Path) -> Result<Vec<(T, PathBuf)>> {
+        use std::fs::read_dir;
+
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let mut dirs: Vec<(T, PathBuf)> = read_dir(path)?
+            .filter_map(|d| d.ok())
+            .filter(|d| d.path().is_dir())
+            .filter_map(|d| {
+                d.file_name()
+                    .to_str()
+                    .and_then(|n| n.parse().ok())
+                    .map(|n| (n, d.path()))
+            })
+            .collect();
+        dirs.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(dirs)
+    }
+
+    /// Writes a committed day under the first free key and returns its path.
+    pub fn write_day(&self, session: &Session, day_commit: &DayCommit) -> Result<PathBuf> {
+        let mut path = self.session_path(session)?;
+        path.push("working");
+        create_dir_all(&path)?;
+
+        let path = FsJsonStore::free_day_path(path, day_commit.date.2);
+        FsJsonStore::write_atomic(&path, day_commit)?;
+        Ok(path)
+    }
+
+    /// Every session that has a directory.
+    pub fn get_sessions(&self) -> Result<Vec<Session>> {
+        let mut path = self.data_path.clone();
+        path.push("sessions");
+
+        let mut sessions = vec![];
+        for (team_id, team_path) in FsJsonStore::child_dirs::<String>(&path)? {
+            for (channel_id, _) in FsJsonStore::child_dirs::<String>(&team_path)? {
+                sessions.push(Session {
+                    team_id: team_id.clone(),
+                    channel_id,
+                });
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Moves data written before sessions existed (`working.json`,
+    /// `working/` and `<year>/` directly under `data_path`) into `session`.
+    pub fn migrate_legacy(&self, session: &Session) -> Result<()> {
+        let target = self.session_path(session)?;
+
+        let mut legacy: Vec<PathBuf> = FsJsonStore::child_dirs::<i32>(&self.data_path)?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect();
+        for name in &["working.json", "working"] {
+            let mut path = self.data_path.clone();
+            path.push(name);
+            if path.exists() {
+                legacy.push(path);
+            }
+        }
+        if legacy.is_empty() {
+            return Ok(());
+        }
+
+        create_dir_all(&target)?;
+        for origin in legacy {
+            let mut path = target.clone();
+            path.push(origin.file_name().unwrap());
+            if path.exists() {
+                bail!(ErrorKind::AlreadyInitialized);
+            }
+            rename(&origin, path)?;
+        }
+        File::open(&target)?.sync_all()?;
+        File::open(&self.data_path)?.sync_all()?;
+        Ok(())
+    }
+
+    fn month_path(&self, session: &Session, year: i32, month: u32) -> Result<PathBuf> {
+        let mut path = self.session_path(session)?;
+        path.push(year.to_string());
+        path.push(month.to_string());
+        Ok(path)
+    }
+
+    /// Every archived month of `session` under `<year>/<month>/`, oldest first.
+    pub fn get_archives(&self, session: &Session) -> Result<Vec<MonthCommits>> {
+        let mut archives = vec![];
+        for (year, month) in self.get_months(session)? {
+            archives.push(((year, month), self.get_month(session, year, month)?));
+        }
+        Ok(archives)
+    }
+}
+
+/// Ids and branch names become path components, so only letters, digits,
+/// `_` and `-` are allowed.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+impl Store for FsJsonStore {
+    fn lock(&self, session: &Session) -> Result<StoreLock> {
+        let mut path = self.session_path(session)?;
+        create_dir_all(&path)?;
+        path.push(".lock");
+
+        let file = OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+        file.lock_exclusive()?;
+        Ok(Box::new(file))
+    }
+
+    fn create_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let path = self.working_file_path(session, branch)?;
+
+        if path.exists() {
+            bail!(ErrorKind::AlreadyInitialized);
+        }
+
+        create_dir_all(path.parent().unwrap())?;
+        FsJsonStore::write_atomic(&path, day_commit)
+    }
+
+    fn get_working(&self, session: &Session, branch: Option<&str>) -> Result<DayCommit> {
+        let file = self.get_working_file(session, branch, OpenOptions::new().read(true))?;
+        FsJsonStore::get_commit_from_file(&file)
+    }
+
+    fn get_branches(&self, session: &Session) -> Result<Vec<(Option<String>, DayCommit)>> {
+        use std::fs::read_dir;
+
+        let mut branches = vec![];
+        let path = self.working_file_path(session, None)?;
+        if path.exists() {
+            branches.push((None, FsJsonStore::get_commit_from_path(path)?));
+        }
+
+        let mut path = self.session_path(session)?;
+        path.push("branches");
+        if !path.exists() {
+            return Ok(branches);
+        }
+        let mut names: Vec<String> = read_dir(&path)?
+            .filter_map(|d| d.ok())
+            .map(|d| d.path())
+            .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+            .filter_map(|p| p.file_stem().and_then(|s| s.to_str()).map(|s| s.to_owned()))
+            .collect();
+        names.sort();
+        for name in names {
+            let day_commit = self.get_working(session, Some(&name))?;
+            branches.push((Some(name), day_commit));
+        }
+        Ok(branches)
+    }
+
+    fn put_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let path = self.working_file_path(session, branch)?;
+
+        if !path.exists() {
+            bail!(ErrorKind::NotInitialized);
+        }
+
+        FsJsonStore::write_atomic(&path, day_commit)
+    }
+
+    fn remove_working(&self, session: &Session, branch: Option<&str>) -> Result<()> {
+        use std::fs::remove_file;
+
+        let path = self.working_file_path(session, branch)?;
+
+        if !path.exists() {
+            bail!(ErrorKind::NotInitialized);
+        }
+        remove_file(&path)?;
+        FsJsonStore::sync_parent(&path)
+    }
+
+    fn stash_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        use std::fs::remove_file;
+
+        let path = self.working_file_path(session, branch)?;
+        if !path.exists() {
+            bail!(ErrorKind::NotInitialized);
+        }
+
+        let mut stashes = self.get_stashes(session)?;
+        stashes.insert(0, day_commit.clone());
+        FsJsonStore::write_atomic(&self.stash_path(session)?, &stashes)?;
+
+        remove_file(&path)?;
+        FsJsonStore::sync_parent(&path)
+    }
+
+    fn get_stashes(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        let path = self.stash_path(session)?;
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(|e| ErrorKind::Json(e).into())
+    }
+
+    fn pop_stash(
+        &self,
+        session: &Session,
+        n: usize,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        use std::fs::remove_file;
+
+        let mut stashes = self.get_stashes(session)?;
+        if n >= stashes.len() {
+            bail!(ErrorKind::NoSuchStash(n));
+        }
+        stashes.remove(n);
+
+        self.create_working(session, branch, day_commit)?;
+
+        let path = self.stash_path(session)?;
+        if stashes.is_empty() {
+            remove_file(&path)?;
+            FsJsonStore::sync_parent(&path)
+        } else {
+            FsJsonStore::write_atomic(&path, &stashes)
+        }
+    }
+
+    fn commit_day(&self, session: &Session, day_commit: &DayCommit) -> Result<()> {
+        self.write_day(session, day_commit).map(|_| ())
+    }
+
+    fn get_days(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        let dir = self.get_working_directory_entries(session)?;
+        FsJsonStore::get_commits_from_entries(dir)
+    }
+
+    fn get_keyed_days(&self, session: &Session) -> Result<Vec<(DayKey, DayCommit)>> {
+        let dir = self.get_working_directory_entries(session)?;
+        let mut days = vec![];
+        for d in dir {
+            let key = FsJsonStore::day_file_key(&d.path()).unwrap();
+            days.push((key, FsJsonStore::get_commit_from_path(d.path())?));
+        }
+        Ok(days)
+    }
+
+    fn put_day(&self, session: &Session, key: &DayKey, day_commit: &DayCommit) -> Result<()> {
+        let path = self.day_file_path(session, key)?;
+        if !path.exists() {
+            bail!(ErrorKind::NoSuchDay(key.to_string()));
+        }
+        FsJsonStore::write_atomic(&path, day_commit)
+    }
+
+    fn remove_day(&self, session: &Session, key: &DayKey) -> Result<()> {
+        use std::fs::remove_file;
+
+        let path = self.day_file_path(session, key)?;
+        if !path.exists() {
+            bail!(ErrorKind::NoSuchDay(key.to_string()));
+        }
+        remove_file(&path)?;
+
+        let mut n = key.n;
+        loop {
+            let next = DayKey {
+                day: key.day,
+                n: n + 1,
+            };
+            let next_path = self.day_file_path(session, &next)?;
+            if !next_path.exists() {
+                break;
+            }
+            rename(next_path, self.day_file_path(session, &DayKey { day: key.day, n })?)?;
+            n += 1;
+        }
+        FsJsonStore::sync_parent(&path)
+    }
+
+    fn push_month(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let dir = self.get_working_directory_entries(session)?;
+        if dir.is_empty() {
+            bail!(ErrorKind::NotInitialized);
+        }
+
+        // Read everything first, so a broken file stops the push before anything moves
+        let mut moves = vec![];
+        for d in dir {
+            let day_commit = FsJsonStore::get_commit_from_path(d.path())?;
+            moves.push((d.path(), day_commit.date));
+        }
+
+        let mut months = vec![];
+        for (origin, date) in moves {
+            let path = self.month_path(session, date.0, date.1)?;
+            create_dir_all(&path)?;
+            rename(&origin, FsJsonStore::free_day_path(path, date.2))?;
+            if !months.contains(&(date.0, date.1)) {
+                months.push((date.0, date.1));
+            }
+        }
+
+        for &(year, month) in &months {
+            File::open(self.month_path(session, year, month)?)?.sync_all()?;
+        }
+        let mut working = self.session_path(session)?;
+        working.push("working");
+        File::open(working)?.sync_all()?;
+
+        months.sort();
+        Ok(months)
+    }
+
+    fn get_month(&self, session: &Session, year: i32, month: u32) -> Result<Vec<DayCommit>> {
+        let path = self.month_path(session, year, month)?;
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let dir = FsJsonStore::get_day_entries(&path)?;
+        FsJsonStore::get_commits_from_entries(dir)
+    }
+
+    fn get_months(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let mut months = vec![];
+        for (year, year_path) in FsJsonStore::child_dirs::<i32>(&self.session_path(session)?)? {
+            for (month, _) in FsJsonStore::child_dirs::<u32>(&year_path)? {
+                months.push((year, month));
+            }
+        }
+        Ok(months)
+    }
+
+    fn unpush_month(&self, session: &Session, year: i32, month: u32) -> Result<()> {
+        use std::fs::{read_dir, remove_dir};
+
+        let path = self.month_path(session, year, month)?;
+        if !path.exists() {
+            bail!(ErrorKind::NotArchived(year, month));
+        }
+
+        let mut working = self.session_path(session)?;
+        working.push("working");
+        create_dir_all(&working)?;
+
+        for d in FsJsonStore::get_day_entries(&path)? {
+            let origin = d.path();
+            let key = FsJsonStore::day_file_key(&origin).unwrap();
+            rename(&origin, FsJsonStore::free_day_path(working.clone(), key.day))?;
+        }
+        File::open(&working)?.sync_all()?;
+
+        if read_dir(&path)?.next().is_none() {
+            remove_dir(&path)?;
+            let year_path = path.parent().unwrap();
+            if read_dir(year_path)?.next().is_none() {
+                remove_dir(year_path)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,588 @@
+use app::{DayCommit, DayKey, Session};
+use error::{Error, ErrorKind, Result};
+use super::{FsJsonStore, Store, StoreLock};
+use std::fs::{read_to_string, remove_file, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+const GITIGNORE: &str = ".lock
+*.tmp
+working.json
+branches/
+stash.json
+push.pending
+*.sqlite3
+";
+
+/// `FsJsonStore` whose `data_path` is a git repository.
+///
+/// Every committed day becomes a git commit, with the shift message as the
+/// subject and the participants as trailers. Every pushed month becomes a tag
+/// `<team_id>/<channel_id>/<year>-<month>`, which is pushed to `remote` along
+/// with the current branch if one is configured. Tags are never moved: a
+/// month pushed again after an unpush is tagged `<year>-<month>.2`, `.3`, ...
+///
+/// A change whose git step fails is rolled back to `HEAD`. Tags and the push
+/// that fail after a month is archived are kept in `push.pending` and retried
+/// by the next push.
+pub struct GitStore {
+    inner: FsJsonStore,
+    data_path: PathBuf,
+    remote: Option<String>,
+    git_lock: Mutex<()>,
+}
+
+impl GitStore {
+    /// Runs `git init` on `data_path` if it isn't a repository yet. `remote`
+    /// must be an existing local path or a `file://` URL.
+    pub fn open<P: Into<PathBuf>>(data_path: P, remote: Option<String>) -> Result<GitStore> {
+        let data_path = data_path.into();
+
+        if let Some(ref remote) = remote {
+            if !is_local_remote(remote) {
+                bail!(ErrorKind::InvalidSetting("git_remote", remote.clone()));
+            }
+        }
+
+        let store = GitStore {
+            inner: FsJsonStore::new(data_path.clone()),
+            data_path,
+            remote,
+            git_lock: Mutex::new(()),
+        };
+
+        if !store.data_path.join(".git").exists() {
+            store.git(&["init", "--quiet"])?;
+        }
+        // Repositories from older versions lack the later entries
+        let gitignore = store.data_path.join(".gitignore");
+        let existing = if gitignore.exists() {
+            read_to_string(&gitignore)?
+        } else {
+            String::new()
+        };
+        let missing: Vec<&str> = GITIGNORE
+            .lines()
+            .filter(|l| !existing.lines().any(|e| e == *l))
+            .collect();
+        if !missing.is_empty() {
+            let mut lines = missing.join("\n") + "\n";
+            if !existing.is_empty() && !existing.ends_with('\n') {
+                lines.insert(0, '\n');
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&gitignore)?
+                .write_all(lines.as_bytes())?;
+            store.git(&["add", "--", ".gitignore"])?;
+            store.git(&["commit", "--quiet", "-m", "Ignore glt temporary files"])?;
+        }
+
+        Ok(store)
+    }
+
+    pub fn inner(&self) -> &FsJsonStore {
+        &self.inner
+    }
+
+    fn git(&self, args: &[&str]) -> Result<()> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.data_path)
+            .args(&["-c", "user.name=glt", "-c", "user.email=glt@localhost"])
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            bail!(ErrorKind::GitFailed(
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether git exits with success, for commands that answer with it.
+    fn git_succeeds(&self, args: &[&str]) -> Result<bool> {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&self.data_path)
+            .args(args)
+            .output()?
+            .status;
+        Ok(status.success())
+    }
+
+    fn head(&self) -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.data_path)
+            .args(&["rev-parse", "HEAD"])
+            .output()?;
+        if !output.status.success() {
+            bail!(ErrorKind::GitFailed(
+                "rev-parse HEAD".to_owned(),
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    }
+
+    fn has_tag(&self, tag: &str) -> Result<bool> {
+        self.git_succeeds(&["rev-parse", "--quiet", "--verify", &format!("refs/tags/{}", tag)])
+    }
+
+    /// `tag`, or `tag.2`, `tag.3`, ... if it is taken.
+    fn free_tag(&self, tag: &str) -> Result<String> {
+        let mut free = tag.to_owned();
+        let mut i = 2;
+        while self.has_tag(&free)? {
+            free = format!("{}.{}", tag, i);
+            i += 1;
+        }
+        Ok(free)
+    }
+
+    /// Stages everything under `dir` and commits it, or nothing if nothing
+    /// changed, like an edit to the same message.
+    fn commit_dir(&self, dir: &str, subject: &str, body: &str) -> Result<()> {
+        // A session with no records yet has nothing for git to add
+        if !self.data_path.join(dir).exists()
+            && !self.git_succeeds(&["ls-files", "--error-unmatch", "--", dir])?
+        {
+            return Ok(());
+        }
+        self.git(&["add", "--all", "--", dir])?;
+        if self.git_succeeds(&["diff", "--cached", "--quiet", "--", dir])? {
+            return Ok(());
+        }
+        self.git(&["commit", "--quiet", "-m", subject, "-m", body, "--", dir])
+    }
+
+    /// Runs `change` on the files of `session` and commits what it did, with
+    /// the subject and body `describe` makes of its result. If either fails,
+    /// the session directory is reset to `HEAD`, so nothing is left behind
+    /// for a later commit to sweep up.
+    fn commit_change<T, C, D>(&self, session: &Session, change: C, describe: D) -> Result<T>
+    where
+        C: FnOnce() -> Result<T>,
+        D: FnOnce(&T) -> (String, String),
+    {
+        let dir = FsJsonStore::session_dir(session)?;
+        let dir = path_str(&dir);
+
+        let _lock = self.git_lock
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("GitStore"))?;
+        // Records git doesn't have yet, like ones from before the store was a
+        // repository, are committed first so the reset can't lose them
+        self.commit_dir(
+            dir,
+            "Add records from outside git",
+            &format!("Session: {}/{}", session.team_id, session.channel_id),
+        )?;
+        let result = change().and_then(|value| {
+            let (subject, body) = describe(&value);
+            self.commit_dir(dir, &subject, &body).map(|_| value)
+        });
+        if result.is_err() {
+            let _ = self.git(&["reset", "--quiet", "--", dir]);
+            let _ = self.git(&["checkout", "--quiet", "--", dir]);
+            let _ = self.git(&["clean", "--quiet", "--force", "-d", "--", dir]);
+        }
+        result
+    }
+
+    fn pending_path(&self, session: &Session) -> Result<PathBuf> {
+        Ok(self.data_path
+            .join(FsJsonStore::session_dir(session)?)
+            .join("push.pending"))
+    }
+
+    /// Tags the months in `push.pending` and pushes them, then removes it.
+    /// Tags made by an earlier try are kept.
+    fn finish_push(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let path = self.pending_path(session)?;
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+
+        let _lock = self.git_lock
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("GitStore"))?;
+        let mut months = vec![];
+        let mut refs = vec!["HEAD".to_owned()];
+        for line in read_to_string(&path)?.lines() {
+            let words: Vec<&str> = line.split(' ').collect();
+            let (year, month, tag, commit) = match (
+                words.first().and_then(|w| w.parse().ok()),
+                words.get(1).and_then(|w| w.parse().ok()),
+                words.get(2),
+                words.get(3),
+            ) {
+                (Some(year), Some(month), Some(tag), Some(commit)) => (year, month, tag, commit),
+                _ => bail!(ErrorKind::BrokenRecord(path.display().to_string())),
+            };
+            if !self.has_tag(tag)? {
+                self.git(&["tag", "-a", tag, "-m", tag, commit])?;
+            }
+            refs.push(format!("refs/tags/{}", tag));
+            months.push((year, month));
+        }
+        // A remote that has moved on rejects the push with `GitFailed`, for an
+        // operator to reconcile rather than to be overwritten
+        if let Some(ref remote) = self.remote {
+            let mut args = vec!["push", "--quiet", remote.as_str()];
+            args.extend(refs.iter().map(|r| r.as_str()));
+            self.git(&args)?;
+        }
+        remove_file(&path)?;
+        Ok(months)
+    }
+
+    fn trailers(session: &Session, day_commit: &DayCommit) -> String {
+        let mut s = format!(
+            "Session: {}/{}\nStart: {}",
+            session.team_id,
+            session.channel_id,
+            day_commit.start_time.to_short_str()
+        );
+        if let Some(ref end) = day_commit.end() {
+            s = s + &format!("\nEnd: {}", day_commit.date.short_str(end));
+        }
+        for b in &day_commit.breaks {
+            s = s + &format!("\nBreak: {}", b.to_str(&day_commit.date));
+        }
+        for p in &day_commit.participants {
+            s = s + &format!(
+                "\nParticipant: {} ({})",
+                p.name,
+                p.stays_str(&day_commit.date)
+            );
+        }
+        s
+    }
+}
+
+fn path_str(path: &Path) -> &str {
+    // session directories are validated to be plain ASCII
+    path.to_str().unwrap()
+}
+
+/// A `file://` URL, or a path that exists and git doesn't read as an SSH
+/// `host:path`, which it does unless a `/` comes before the `:`.
+fn is_local_remote(remote: &str) -> bool {
+    if remote.starts_with("file://") {
+        return true;
+    }
+    let scp_like = match remote.find(':') {
+        Some(i) => !remote[..i].contains('/'),
+        None => false,
+    };
+    !scp_like && Path::new(remote).exists()
+}
+
+impl Store for GitStore {
+    fn lock(&self, session: &Session) -> Result<StoreLock> {
+        self.inner.lock(session)
+    }
+
+    fn create_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        self.inner.create_working(session, branch, day_commit)
+    }
+
+    fn get_working(&self, session: &Session, branch: Option<&str>) -> Result<DayCommit> {
+        self.inner.get_working(session, branch)
+    }
+
+    fn get_branches(&self, session: &Session) -> Result<Vec<(Option<String>, DayCommit)>> {
+        self.inner.get_branches(session)
+    }
+
+    fn put_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        self.inner.put_working(session, branch, day_commit)
+    }
+
+    fn remove_working(&self, session: &Session, branch: Option<&str>) -> Result<()> {
+        self.inner.remove_working(session, branch)
+    }
+
+    fn stash_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        self.inner.stash_working(session, branch, day_commit)
+    }
+
+    fn get_stashes(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        self.inner.get_stashes(session)
+    }
+
+    fn pop_stash(
+        &self,
+        session: &Session,
+        n: usize,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        self.inner.pop_stash(session, n, branch, day_commit)
+    }
+
+    fn commit_day(&self, session: &Session, day_commit: &DayCommit) -> Result<()> {
+        self.commit_change(
+            session,
+            || self.inner.write_day(session, day_commit),
+            |_| {
+                let subject = format!(
+                    "{} {}",
+                    day_commit.date,
+                    day_commit.message.clone().unwrap_or_default()
+                );
+                (subject, GitStore::trailers(session, day_commit))
+            },
+        )?;
+        Ok(())
+    }
+
+    fn get_days(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        self.inner.get_days(session)
+    }
+
+    fn get_keyed_days(&self, session: &Session) -> Result<Vec<(DayKey, DayCommit)>> {
+        self.inner.get_keyed_days(session)
+    }
+
+    fn put_day(&self, session: &Session, key: &DayKey, day_commit: &DayCommit) -> Result<()> {
+        self.commit_change(
+            session,
+            || self.inner.put_day(session, key, day_commit),
+            |_| {
+                let subject = format!(
+                    "Amend {} {}",
+                    day_commit.date,
+                    day_commit.message.clone().unwrap_or_default()
+                );
+                (subject, GitStore::trailers(session, day_commit))
+            },
+        )
+    }
+
+    fn remove_day(&self, session: &Session, key: &DayKey) -> Result<()> {
+        self.commit_change(
+            session,
+            || self.inner.remove_day(session, key),
+            |_| {
+                (
+                    format!("Drop {}", key),
+                    format!("Session: {}/{}", session.team_id, session.channel_id),
+                )
+            },
+        )
+    }
+
+    /// Finishes an earlier push whose tags or upload failed before archiving
+    /// anything new.
+    fn push_month(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let mut months = self.finish_push(session)?;
+
+        let archived = self.commit_change(
+            session,
+            || self.inner.push_month(session),
+            |months| {
+                let names: Vec<String> = months
+                    .iter()
+                    .map(|&(year, month)| format!("{}-{:02}", year, month))
+                    .collect();
+                (
+                    format!("Push {}", names.join(", ")),
+                    format!("Session: {}/{}", session.team_id, session.channel_id),
+                )
+            },
+        );
+        let archived = match archived {
+            Err(Error(ErrorKind::NotInitialized, _)) if !months.is_empty() => return Ok(months),
+            r => r?,
+        };
+
+        {
+            let _lock = self.git_lock
+                .lock()
+                .map_err(|_| ErrorKind::Poisoned("GitStore"))?;
+            let head = self.head()?;
+            let mut pending = String::new();
+            for &(year, month) in &archived {
+                let tag = self.free_tag(&format!(
+                    "{}/{}/{}-{:02}",
+                    session.team_id, session.channel_id, year, month
+                ))?;
+                pending = pending + &format!("{} {} {} {}\n", year, month, tag, head);
+            }
+            File::create(self.pending_path(session)?)?.write_all(pending.as_bytes())?;
+        }
+        months.extend(self.finish_push(session)?);
+        months.sort();
+        months.dedup();
+        Ok(months)
+    }
+
+    fn get_month(&self, session: &Session, year: i32, month: u32) -> Result<Vec<DayCommit>> {
+        self.inner.get_month(session, year, month)
+    }
+
+    fn get_months(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        self.inner.get_months(session)
+    }
+
+    fn unpush_month(&self, session: &Session, year: i32, month: u32) -> Result<()> {
+        self.commit_change(
+            session,
+            || self.inner.unpush_month(session, year, month),
+            |_| {
+                (
+                    format!("Unpush {}-{:02}", year, month),
+                    format!("Session: {}/{}", session.team_id, session.channel_id),
+                )
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use std::env;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("glt-git-{}", name));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn session() -> Session {
+        Session {
+            team_id: "T1".to_owned(),
+            channel_id: "C1".to_owned(),
+        }
+    }
+
+    fn day(message: &str) -> DayCommit {
+        serde_json::from_str(&format!(
+            r#"{{"date": [2018, 3, 2], "start_time": [9, 0], "end_time": [18, 0],
+                "message": "{}", "participants": []}}"#,
+            message
+        )).unwrap()
+    }
+
+    fn commit_count(path: &Path) -> usize {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(&["rev-list", "--count", "HEAD"])
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().parse().unwrap()
+    }
+
+    fn is_clean(path: &Path) -> bool {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(&["status", "--porcelain"])
+            .output()
+            .unwrap();
+        output.stdout.is_empty()
+    }
+
+    #[test]
+    fn local_remotes() {
+        let dir = temp_dir("remote");
+        assert!(is_local_remote(dir.to_str().unwrap()));
+        assert!(is_local_remote("file:///srv/glt.git"));
+        assert!(!is_local_remote("/no/such/dir"));
+        assert!(!is_local_remote("https://example.com/glt.git"));
+        assert!(!is_local_remote("git@example.com:glt.git"));
+        assert!(!is_local_remote("example.com:glt"));
+        assert!(GitStore::open(temp_dir("ssh"), Some("git@example.com:glt".to_owned())).is_err());
+    }
+
+    #[test]
+    fn unchanged_edit_commits_nothing() {
+        let path = temp_dir("unchanged");
+        let store = GitStore::open(&path, None).unwrap();
+        store.commit_day(&session(), &day("a")).unwrap();
+        let commits = commit_count(&path);
+        let (key, day_commit) = store.get_keyed_days(&session()).unwrap().remove(0);
+        store.put_day(&session(), &key, &day_commit).unwrap();
+        assert_eq!(commit_count(&path), commits);
+    }
+
+    #[test]
+    fn failed_git_rolls_back() {
+        let path = temp_dir("rollback");
+        let store = GitStore::open(&path, None).unwrap();
+        store.commit_day(&session(), &day("a")).unwrap();
+        let (key, _) = store.get_keyed_days(&session()).unwrap().remove(0);
+
+        let lock = path.join(".git").join("index.lock");
+        File::create(&lock).unwrap();
+        assert!(store.put_day(&session(), &key, &day("b")).is_err());
+        assert!(store.remove_day(&session(), &key).is_err());
+        assert!(store.commit_day(&session(), &day("c")).is_err());
+        assert!(store.push_month(&session()).is_err());
+        remove_file(&lock).unwrap();
+
+        let days = store.get_days(&session()).unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].message, Some("a".to_owned()));
+        assert!(store.get_months(&session()).unwrap().is_empty());
+        assert!(is_clean(&path));
+    }
+
+    #[test]
+    fn failed_push_is_retried() {
+        let remote = temp_dir("retry-remote");
+        let path = temp_dir("retry");
+        let store = GitStore::open(&path, Some(remote.to_str().unwrap().to_owned())).unwrap();
+        store.commit_day(&session(), &day("a")).unwrap();
+
+        // not a repository yet, so the push fails after the month is archived
+        assert!(store.push_month(&session()).is_err());
+        assert_eq!(store.get_months(&session()).unwrap(), [(2018, 3)]);
+
+        Command::new("git")
+            .args(&["init", "--quiet", "--bare"])
+            .arg(&remote)
+            .status()
+            .unwrap();
+        assert_eq!(store.push_month(&session()).unwrap(), [(2018, 3)]);
+        assert!(!store.pending_path(&session()).unwrap().exists());
+        let tag = Command::new("git")
+            .arg("-C")
+            .arg(&remote)
+            .args(&["rev-parse", "--verify", "--quiet", "refs/tags/T1/C1/2018-03"])
+            .status()
+            .unwrap();
+        assert!(tag.success());
+        match store.push_month(&session()) {
+            Err(Error(ErrorKind::NotInitialized, _)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use app::{group_by_month, key_days, DayCommit, DayKey, Session};
+use error::{ErrorKind, Result};
+use super::{Store, StoreLock};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Keeps everything in memory. Nothing survives a restart; meant for tests.
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<Session, MemoryState>>,
+    write_lock: Mutex<()>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    working: BTreeMap<Option<String>, DayCommit>,
+    /// The latest last
+    stashes: Vec<DayCommit>,
+    days: Option<Vec<DayCommit>>,
+    months: BTreeMap<(i32, u32), Vec<DayCommit>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    fn index_of(days: &[DayCommit], key: &DayKey) -> Result<usize> {
+        let index = days.iter()
+            .enumerate()
+            .filter(|&(_, d)| d.date.2 == key.day)
+            .map(|(i, _)| i)
+            .nth(key.n);
+        match index {
+            Some(i) => Ok(i),
+            None => bail!(ErrorKind::NoSuchDay(key.to_string())),
+        }
+    }
+
+    fn with_state<T, F>(&self, session: &Session, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut MemoryState) -> Result<T>,
+    {
+        let mut sessions = self.sessions
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("MemoryStore"))?;
+        f(sessions.entry(session.clone()).or_insert_with(Default::default))
+    }
+}
+
+impl Store for MemoryStore {
+    fn lock(&self, _session: &Session) -> Result<StoreLock> {
+        let guard = self.write_lock
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("MemoryStore write lock"))?;
+        Ok(Box::new(guard))
+    }
+
+    fn create_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let branch = branch.map(|b| b.to_owned());
+        self.with_state(session, |state| {
+            if state.working.contains_key(&branch) {
+                bail!(ErrorKind::AlreadyInitialized);
+            }
+            state.working.insert(branch, day_commit.clone());
+            Ok(())
+        })
+    }
+
+    fn get_working(&self, session: &Session, branch: Option<&str>) -> Result<DayCommit> {
+        let branch = branch.map(|b| b.to_owned());
+        self.with_state(session, |state| match state.working.get(&branch) {
+            Some(day_commit) => Ok(day_commit.clone()),
+            None => bail!(ErrorKind::NotInitialized),
+        })
+    }
+
+    fn get_branches(&self, session: &Session) -> Result<Vec<(Option<String>, DayCommit)>> {
+        self.with_state(session, |state| {
+            Ok(state
+                .working
+                .iter()
+                .map(|(branch, day_commit)| (branch.clone(), day_commit.clone()))
+                .collect())
+        })
+    }
+
+    fn put_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let branch = branch.map(|b| b.to_owned());
+        self.with_state(session, |state| {
+            if !state.working.contains_key(&branch) {
+                bail!(ErrorKind::NotInitialized);
+            }
+            state.working.insert(branch, day_commit.clone());
+            Ok(())
+        })
+    }
+
+    fn remove_working(&self, session: &Session, branch: Option<&str>) -> Result<()> {
+        let branch = branch.map(|b| b.to_owned());
+        self.with_state(session, |state| {
+            if state.working.remove(&branch).is_none() {
+                bail!(ErrorKind::NotInitialized);
+            }
+            Ok(())
+        })
+    }
+
+    fn stash_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let branch = branch.map(|b| b.to_owned());
+        self.with_state(session, |state| {
+            if state.working.remove(&branch).is_none() {
+                bail!(ErrorKind::NotInitialized);
+            }
+            state.stashes.push(day_commit.clone());
+            Ok(())
+        })
+    }
+
+    fn get_stashes(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        self.with_state(session, |state| Ok(state.stashes.iter().rev().cloned().collect()))
+    }
+
+    fn pop_stash(
+        &self,
+        session: &Session,
+        n: usize,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let branch = branch.map(|b| b.to_owned());
+        self.with_state(session, |state| {
+            if n >= state.stashes.len() {
+                bail!(ErrorKind::NoSuchStash(n));
+            }
+            if state.working.contains_key(&branch) {
+                bail!(ErrorKind::AlreadyInitialized);
+            }
+            let i = state.stashes.len() - 1 - n;
+            state.stashes.remove(i);
+            state.working.insert(branch, day_commit.clone());
+            Ok(())
+        })
+    }
+
+    fn commit_day(&self, session: &Session, day_commit: &DayCommit) -> Result<()> {
+        self.with_state(session, |state| {
+            state
+                .days
+                .get_or_insert_with(Vec::new)
+                .push(day_commit.clone());
+            Ok(())
+        })
+    }
+
+    fn get_days(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        self.with_state(session, |state| match state.days {
+            Some(ref days) => Ok(days.clone()),
+            None => bail!(ErrorKind::NotInitialized),
+        })
+    }
+
+    fn get_keyed_days(&self, session: &Session) -> Result<Vec<(DayKey, DayCommit)>> {
+        self.get_days(session).map(key_days)
+    }
+
+    fn put_day(&self, session: &Session, key: &DayKey, day_commit: &DayCommit) -> Result<()> {
+        self.with_state(session, |state| {
+            let days = state.days.get_or_insert_with(Vec::new);
+            let i = MemoryStore::index_of(days, key)?;
+            days[i] = day_commit.clone();
+            Ok(())
+        })
+    }
+
+    fn remove_day(&self, session: &Session, key: &DayKey) -> Result<()> {
+        self.with_state(session, |state| {
+            let days = state.days.get_or_insert_with(Vec::new);
+            let i = MemoryStore::index_of(days, key)?;
+            days.remove(i);
+            Ok(())
+        })
+    }
+
+    fn push_month(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        self.with_state(session, |state| {
+            let days = match state.days {
+                Some(ref mut days) if !days.is_empty() => ::std::mem::replace(days, vec![]),
+                _ => bail!(ErrorKind::NotInitialized),
+            };
+            let mut months = vec![];
+            for (key, days) in group_by_month(days) {
+                state
+                    .months
+                    .entry(key)
+                    .or_insert_with(Vec::new)
+                    .extend(days);
+                months.push(key);
+            }
+            Ok(months)
+        })
+    }
+
+    fn get_month(&self, session: &Session, year: i32, month: u32) -> Result<Vec<DayCommit>> {
+        self.with_state(session, |state| {
+            Ok(state.months.get(&(year, month)).cloned().unwrap_or_default())
+        })
+    }
+
+    fn get_months(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        self.with_state(session, |state| Ok(state.months.keys().cloned().collect()))
+    }
+
+    fn unpush_month(&self, session: &Session, year: i32, month: u32) -> Result<()> {
+        self.with_state(session, |state| {
+            let archived = match state.months.remove(&(year, month)) {
+                Some(archived) => archived,
+                None => bail!(ErrorKind::NotArchived(year, month)),
+            };
+            state
+                .days
+                .get_or_insert_with(Vec::new)
+                .extend(archived);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,318 @@
+pub mod fs_json;
+pub use self::fs_json::FsJsonStore;
+
+pub mod memory;
+pub use self::memory::MemoryStore;
+
+pub mod sqlite;
+pub use self::sqlite::SqliteStore;
+
+pub mod git;
+pub use self::git::GitStore;
+
+use app::{DayCommit, DayKey, Session};
+use error::{ErrorKind, Result};
+use settings::Settings;
+
+/// Opens the backend selected by the `storage` setting.
+pub fn open(settings: &Settings) -> Result<Box<Store + Send + Sync>> {
+    match settings.storage.as_str() {
+        "json" => {
+            let store = FsJsonStore::new(settings.data_path.clone());
+            store.migrate_legacy(&settings.default_session())?;
+            Ok(Box::new(store))
+        }
+        "git" => {
+            let store = GitStore::open(settings.data_path.clone(), settings.git_remote.clone())?;
+            store.inner().migrate_legacy(&settings.default_session())?;
+            Ok(Box::new(store))
+        }
+        "sqlite" => Ok(Box::new(SqliteStore::open(
+            settings.sqlite_path(),
+            &settings.default_session(),
+        )?)),
+        s => bail!(ErrorKind::InvalidSetting("storage", s.to_owned())),
+    }
+}
+
+/// Held while a read-modify-write cycle runs; released on drop.
+pub type StoreLock<'a> = Box<LockGuard + 'a>;
+
+pub trait LockGuard {}
+impl<T> LockGuard for T {}
+
+/// Persistence backend behind `App`.
+///
+/// For every session, a store holds the working (not yet committed) shifts, the
+/// committed days of the current month and the archived months. Sessions never
+/// see each other's data.
+///
+/// Working shifts run side by side on branches. `None` is the default branch,
+/// the one used when no branch is named. Shifts set aside with `glt stash`
+/// wait on a stack of their own until they are popped.
+pub trait Store {
+    /// Takes the store-wide write lock. `App` holds it around every
+    /// read-modify-write cycle, so concurrent requests can't lose updates.
+    /// Not reentrant.
+    fn lock(&self, session: &Session) -> Result<StoreLock>;
+
+    /// Saves a new working shift on `branch`. Fails with `AlreadyInitialized`
+    /// if the branch already has one.
+    fn create_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()>;
+    /// Fails with `NotInitialized` if there is no working shift on `branch`.
+    fn get_working(&self, session: &Session, branch: Option<&str>) -> Result<DayCommit>;
+    /// Every working shift with its branch, the default branch first and the
+    /// others by name.
+    fn get_branches(&self, session: &Session) -> Result<Vec<(Option<String>, DayCommit)>>;
+    /// Overwrites the working shift on `branch`. Fails with `NotInitialized` if
+    /// there is none.
+    fn put_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()>;
+    /// Fails with `NotInitialized` if there is no working shift on `branch`.
+    fn remove_working(&self, session: &Session, branch: Option<&str>) -> Result<()>;
+
+    /// Moves the working shift on `branch` on top of the stash, as
+    /// `day_commit`. Fails with `NotInitialized` if there is none.
+    fn stash_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()>;
+    /// Stashed shifts, the latest first.
+    fn get_stashes(&self, session: &Session) -> Result<Vec<DayCommit>>;
+    /// Moves the `n`th shift of the stash, counting from the latest, onto
+    /// `branch` as `day_commit`. Fails with `NoSuchStash` if there are not that
+    /// many, or with `AlreadyInitialized` if the branch has a working shift.
+    fn pop_stash(
+        &self,
+        session: &Session,
+        n: usize,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()>;
+
+    /// Appends a finished shift to the current month.
+    fn commit_day(&self, session: &Session, day_commit: &DayCommit) -> Result<()>;
+    /// Fails with `NotInitialized` if nothing was ever committed.
+    fn get_days(&self, session: &Session) -> Result<Vec<DayCommit>>;
+    /// Same as `get_days`, with the key of every day.
+    fn get_keyed_days(&self, session: &Session) -> Result<Vec<(DayKey, DayCommit)>>;
+    /// Rewrites a committed day in place. Fails with `NoSuchDay` if there is
+    /// no day with `key`.
+    fn put_day(&self, session: &Session, key: &DayKey, day_commit: &DayCommit) -> Result<()>;
+    /// Deletes a committed day. Later shifts of the same day move up, so
+    /// dropping `3_1` makes `3_2` the new `3_1`. Fails with `NoSuchDay`.
+    fn remove_day(&self, session: &Session, key: &DayKey) -> Result<()>;
+
+    /// Archives every committed day into the month of its own date, and
+    /// returns those months in order. Fails with `NotInitialized` if there is
+    /// nothing to archive.
+    fn push_month(&self, session: &Session) -> Result<Vec<(i32, u32)>>;
+    /// Days of an archived month, empty if it was never pushed.
+    fn get_month(&self, session: &Session, year: i32, month: u32) -> Result<Vec<DayCommit>>;
+    /// Every archived month, oldest first.
+    fn get_months(&self, session: &Session) -> Result<Vec<(i32, u32)>>;
+    /// Moves an archived month back into the current month. Fails with
+    /// `NotArchived` if it was never pushed.
+    fn unpush_month(&self, session: &Session, year: i32, month: u32) -> Result<()>;
+}
+
+/// The same steps against every backend but git, which only adds commits on
+/// top of `FsJsonStore` and has tests of its own.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+    use serde_json;
+    use std::env;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    macro_rules! assert_fails {
+        ($result:expr, $kind:pat) => {
+            match $result {
+                Err(Error($kind, _)) => {}
+                r => panic!("{:?}", r.map(|_| ())),
+            }
+        };
+    }
+
+    fn session(channel_id: &str) -> Session {
+        Session {
+            team_id: "T1".to_owned(),
+            channel_id: channel_id.to_owned(),
+        }
+    }
+
+    fn day(date: (i32, u32, u32), message: &str) -> DayCommit {
+        serde_json::from_str(&format!(
+            r#"{{"date": [{}, {}, {}], "start_time": [9, 0], "end_time": [18, 0],
+                "message": "{}", "participants": []}}"#,
+            date.0, date.1, date.2, message
+        )).unwrap()
+    }
+
+    fn messages(days: &[DayCommit]) -> Vec<&str> {
+        days.iter()
+            .map(|d| d.message.as_ref().map(|m| m.as_str()).unwrap_or(""))
+            .collect()
+    }
+
+    fn key(day: u32, n: usize) -> DayKey {
+        DayKey { day, n }
+    }
+
+    /// Keys and messages of the current month. Backends may list days in
+    /// any order, so they are sorted by key.
+    fn keyed_messages(store: &Store, session: &Session) -> Vec<(DayKey, String)> {
+        let mut days: Vec<(DayKey, String)> = store
+            .get_keyed_days(session)
+            .unwrap()
+            .into_iter()
+            .map(|(key, day_commit)| (key, day_commit.message.unwrap_or_default()))
+            .collect();
+        days.sort_by_key(|&(key, _)| key);
+        days
+    }
+
+    fn working(store: &Store, session: &Session) {
+        assert_fails!(store.get_working(session, None), ErrorKind::NotInitialized);
+        store.create_working(session, None, &day((2018, 3, 5), "a")).unwrap();
+        assert_fails!(
+            store.create_working(session, None, &day((2018, 3, 5), "b")),
+            ErrorKind::AlreadyInitialized
+        );
+        let evening = Some("evening");
+        store.create_working(session, evening, &day((2018, 3, 5), "b")).unwrap();
+        store.put_working(session, evening, &day((2018, 3, 5), "c")).unwrap();
+        assert_fails!(
+            store.put_working(session, Some("night"), &day((2018, 3, 5), "c")),
+            ErrorKind::NotInitialized
+        );
+        let (branches, days): (Vec<_>, Vec<_>) =
+            store.get_branches(session).unwrap().into_iter().unzip();
+        assert_eq!(branches, [None, Some("evening".to_owned())]);
+        assert_eq!(messages(&days), ["a", "c"]);
+        assert_eq!(store.get_working(session, evening).unwrap().message, Some("c".to_owned()));
+
+        store.remove_working(session, evening).unwrap();
+        assert_fails!(store.remove_working(session, evening), ErrorKind::NotInitialized);
+    }
+
+    fn stash(store: &Store, session: &Session) {
+        store.stash_working(session, None, &day((2018, 3, 5), "a")).unwrap();
+        assert_fails!(
+            store.stash_working(session, None, &day((2018, 3, 5), "a")),
+            ErrorKind::NotInitialized
+        );
+        let mut night = day((2018, 3, 5), "b");
+        store.create_working(session, Some("night"), &night).unwrap();
+        night.stashed_branch = Some("night".to_owned());
+        store.stash_working(session, Some("night"), &night).unwrap();
+        let stashes = store.get_stashes(session).unwrap();
+        assert_eq!(messages(&stashes), ["b", "a"]);
+        assert_eq!(stashes[0].stashed_branch, Some("night".to_owned()));
+        assert!(store.get_branches(session).unwrap().is_empty());
+
+        assert_fails!(
+            store.pop_stash(session, 2, None, &day((2018, 3, 5), "a")),
+            ErrorKind::NoSuchStash(2)
+        );
+        store.create_working(session, None, &day((2018, 3, 5), "c")).unwrap();
+        assert_fails!(
+            store.pop_stash(session, 1, None, &day((2018, 3, 5), "a")),
+            ErrorKind::AlreadyInitialized
+        );
+        store.remove_working(session, None).unwrap();
+        store.pop_stash(session, 1, None, &day((2018, 3, 5), "a")).unwrap();
+        assert_eq!(store.get_working(session, None).unwrap().message, Some("a".to_owned()));
+        assert_eq!(messages(&store.get_stashes(session).unwrap()), ["b"]);
+        store.remove_working(session, None).unwrap();
+    }
+
+    fn commit(store: &Store, session: &Session) {
+        assert_fails!(store.get_days(session), ErrorKind::NotInitialized);
+        store.commit_day(session, &day((2018, 2, 27), "feb")).unwrap();
+        store.commit_day(session, &day((2018, 3, 2), "mar 1")).unwrap();
+        store.commit_day(session, &day((2018, 3, 2), "mar 2")).unwrap();
+        assert_eq!(
+            keyed_messages(store, session),
+            [
+                (key(2, 0), "mar 1".to_owned()),
+                (key(2, 1), "mar 2".to_owned()),
+                (key(27, 0), "feb".to_owned()),
+            ]
+        );
+
+        store.put_day(session, &key(2, 1), &day((2018, 3, 2), "mar 3")).unwrap();
+        assert_fails!(
+            store.put_day(session, &key(3, 0), &day((2018, 3, 3), "mar 3")),
+            ErrorKind::NoSuchDay(_)
+        );
+        store.remove_day(session, &key(2, 0)).unwrap();
+        assert_fails!(store.remove_day(session, &key(2, 1)), ErrorKind::NoSuchDay(_));
+        assert_eq!(
+            keyed_messages(store, session),
+            [(key(2, 0), "mar 3".to_owned()), (key(27, 0), "feb".to_owned())]
+        );
+    }
+
+    fn push(store: &Store, session: &Session) {
+        assert_eq!(store.push_month(session).unwrap(), [(2018, 2), (2018, 3)]);
+        assert!(store.get_days(session).unwrap().is_empty());
+        assert_fails!(store.push_month(session), ErrorKind::NotInitialized);
+        assert_eq!(store.get_months(session).unwrap(), [(2018, 2), (2018, 3)]);
+        assert_eq!(messages(&store.get_month(session, 2018, 3).unwrap()), ["mar 3"]);
+        assert!(store.get_month(session, 2018, 4).unwrap().is_empty());
+    }
+
+    fn unpush(store: &Store, session: &Session) {
+        store.unpush_month(session, 2018, 3).unwrap();
+        assert_fails!(store.unpush_month(session, 2018, 3), ErrorKind::NotArchived(2018, 3));
+        assert_eq!(messages(&store.get_days(session).unwrap()), ["mar 3"]);
+        assert_eq!(store.get_months(session).unwrap(), [(2018, 2)]);
+        assert!(store.get_month(session, 2018, 3).unwrap().is_empty());
+    }
+
+    fn contract(store: &Store) {
+        let other = session("C2");
+        let session = session("C1");
+        working(store, &session);
+        stash(store, &session);
+        commit(store, &session);
+        push(store, &session);
+        unpush(store, &session);
+
+        assert!(store.get_branches(&other).unwrap().is_empty());
+        assert!(store.get_stashes(&other).unwrap().is_empty());
+        assert_fails!(store.get_days(&other), ErrorKind::NotInitialized);
+        assert!(store.get_months(&other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn memory_store() {
+        contract(&MemoryStore::new());
+    }
+
+    #[test]
+    fn fs_json_store() {
+        let path = env::temp_dir().join("glt-store-contract");
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        contract(&FsJsonStore::new(path));
+    }
+
+    #[test]
+    fn sqlite_store() {
+        contract(&SqliteStore::open(":memory:", &session("C1")).unwrap());
+    }
+}
@@ -0,0 +1,1170 @@
+use app::{group_by_month, key_days, Break, Date, DayCommit, DayKey, Interval, Participant, Session,
+          Time};
+use error::{ErrorKind, Result};
+use super::{FsJsonStore, Store, StoreLock};
+use rusqlite::types::ToSql;
+use rusqlite::{Connection, Row, Transaction};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+const SCHEMA_VERSION: i32 = 8;
+
+const SCHEMA: &str = "
+CREATE TABLE archives (
+    id INTEGER PRIMARY KEY,
+    team_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    month INTEGER NOT NULL,
+    UNIQUE (team_id, channel_id, year, month)
+);
+CREATE TABLE day_commits (
+    id INTEGER PRIMARY KEY,
+    team_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    month INTEGER NOT NULL,
+    day INTEGER NOT NULL,
+    start_hour INTEGER NOT NULL,
+    start_minute INTEGER NOT NULL,
+    end_hour INTEGER,
+    end_minute INTEGER,
+    end_year INTEGER,
+    end_month INTEGER,
+    end_day INTEGER,
+    message TEXT,
+    timezone TEXT,
+    working INTEGER NOT NULL DEFAULT 0,
+    branch TEXT,
+    archive_id INTEGER REFERENCES archives (id),
+    stashed_year INTEGER,
+    stashed_month INTEGER,
+    stashed_day INTEGER,
+    stashed_hour INTEGER,
+    stashed_minute INTEGER
+);
+CREATE INDEX day_commits_session ON day_commits (team_id, channel_id);
+CREATE TABLE participants (
+    id INTEGER PRIMARY KEY,
+    day_commit_id INTEGER NOT NULL REFERENCES day_commits (id),
+    name TEXT NOT NULL,
+    commit_hour INTEGER NOT NULL,
+    commit_minute INTEGER NOT NULL
+);
+CREATE INDEX participants_name ON participants (name);
+CREATE TABLE participant_intervals (
+    id INTEGER PRIMARY KEY,
+    participant_id INTEGER NOT NULL REFERENCES participants (id),
+    in_hour INTEGER NOT NULL,
+    in_minute INTEGER NOT NULL,
+    out_hour INTEGER,
+    out_minute INTEGER,
+    in_year INTEGER,
+    in_month INTEGER,
+    in_day INTEGER,
+    out_year INTEGER,
+    out_month INTEGER,
+    out_day INTEGER
+);
+CREATE INDEX participant_intervals_participant ON participant_intervals (participant_id);
+CREATE TABLE breaks (
+    id INTEGER PRIMARY KEY,
+    day_commit_id INTEGER NOT NULL REFERENCES day_commits (id),
+    participant_id INTEGER REFERENCES participants (id),
+    start_year INTEGER NOT NULL,
+    start_month INTEGER NOT NULL,
+    start_day INTEGER NOT NULL,
+    start_hour INTEGER NOT NULL,
+    start_minute INTEGER NOT NULL,
+    end_year INTEGER,
+    end_month INTEGER,
+    end_day INTEGER,
+    end_hour INTEGER,
+    end_minute INTEGER,
+    reason TEXT
+);
+CREATE INDEX breaks_day_commit ON breaks (day_commit_id);
+";
+
+/// Databases created before sessions existed have no `user_version` and no
+/// session columns. Their rows are moved into the default session.
+const MIGRATE_V1: &str = "
+ALTER TABLE day_commits ADD COLUMN team_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE day_commits ADD COLUMN channel_id TEXT NOT NULL DEFAULT '';
+UPDATE day_commits SET team_id = :team_id, channel_id = :channel_id;
+CREATE INDEX day_commits_session ON day_commits (team_id, channel_id);
+CREATE TABLE archives_v2 (
+    id INTEGER PRIMARY KEY,
+    team_id TEXT NOT NULL,
+    channel_id TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    month INTEGER NOT NULL,
+    UNIQUE (team_id, channel_id, year, month)
+);
+INSERT INTO archives_v2 (id, team_id, channel_id, year, month)
+    SELECT id, :team_id, :channel_id, year, month FROM archives;
+DROP TABLE archives;
+ALTER TABLE archives_v2 RENAME TO archives;
+";
+
+/// Participants of version 2 only have an arrival time, they get a single
+/// stay from it.
+const MIGRATE_V2: &str = "
+CREATE TABLE participant_intervals (
+    id INTEGER PRIMARY KEY,
+    participant_id INTEGER NOT NULL REFERENCES participants (id),
+    in_hour INTEGER NOT NULL,
+    in_minute INTEGER NOT NULL,
+    out_hour INTEGER,
+    out_minute INTEGER
+);
+CREATE INDEX participant_intervals_participant ON participant_intervals (participant_id);
+INSERT INTO participant_intervals (participant_id, in_hour, in_minute)
+    SELECT id, commit_hour, commit_minute FROM participants;
+";
+
+/// Times of version 3 have no dates. Without them they fall on the day the
+/// shift started, which was the only possibility back then.
+const MIGRATE_V3: &str = "
+ALTER TABLE day_commits ADD COLUMN end_year INTEGER;
+ALTER TABLE day_commits ADD COLUMN end_month INTEGER;
+ALTER TABLE day_commits ADD COLUMN end_day INTEGER;
+ALTER TABLE participant_intervals ADD COLUMN in_year INTEGER;
+ALTER TABLE participant_intervals ADD COLUMN in_month INTEGER;
+ALTER TABLE participant_intervals ADD COLUMN in_day INTEGER;
+ALTER TABLE participant_intervals ADD COLUMN out_year INTEGER;
+ALTER TABLE participant_intervals ADD COLUMN out_month INTEGER;
+ALTER TABLE participant_intervals ADD COLUMN out_day INTEGER;
+";
+
+/// Shifts of version 4 have no time zone, their times are counted as they are.
+const MIGRATE_V4: &str = "
+ALTER TABLE day_commits ADD COLUMN timezone TEXT;
+";
+
+/// Breaks are new in version 6, older shifts have none.
+const MIGRATE_V5: &str = "
+CREATE TABLE breaks (
+    id INTEGER PRIMARY KEY,
+    day_commit_id INTEGER NOT NULL REFERENCES day_commits (id),
+    participant_id INTEGER REFERENCES participants (id),
+    start_year INTEGER NOT NULL,
+    start_month INTEGER NOT NULL,
+    start_day INTEGER NOT NULL,
+    start_hour INTEGER NOT NULL,
+    start_minute INTEGER NOT NULL,
+    end_year INTEGER,
+    end_month INTEGER,
+    end_day INTEGER,
+    end_hour INTEGER,
+    end_minute INTEGER,
+    reason TEXT
+);
+CREATE INDEX breaks_day_commit ON breaks (day_commit_id);
+";
+
+/// Working shifts of version 6 are on the default branch.
+const MIGRATE_V6: &str = "
+ALTER TABLE day_commits ADD COLUMN branch TEXT;
+";
+
+/// Shifts stashed by version 7 don't know when they were stashed.
+const MIGRATE_V7: &str = "
+ALTER TABLE day_commits ADD COLUMN stashed_year INTEGER;
+ALTER TABLE day_commits ADD COLUMN stashed_month INTEGER;
+ALTER TABLE day_commits ADD COLUMN stashed_day INTEGER;
+ALTER TABLE day_commits ADD COLUMN stashed_hour INTEGER;
+ALTER TABLE day_commits ADD COLUMN stashed_minute INTEGER;
+";
+
+/// Values of `day_commits.working`
+const COMMITTED: i32 = 0;
+const WORKING: i32 = 1;
+const STASHED: i32 = 2;
+
+const DAY_COMMIT_COLUMNS: &str = "id, year, month, day, start_hour, start_minute, \
+                                  end_hour, end_minute, message, end_year, end_month, end_day, \
+                                  timezone, stashed_year, stashed_month, stashed_day, \
+                                  stashed_hour, stashed_minute, working, branch";
+
+/// Stores day commits and their participants in normalized SQLite tables.
+///
+/// Working shifts are the rows with `working = 1`, with a `NULL` branch on the
+/// default branch, and stashed shifts the ones with `working = 2`, with the
+/// branch they were stashed from. The current month is every committed row
+/// (`working = 0`) without an `archive_id`.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    write_lock: Mutex<()>,
+}
+
+impl SqliteStore {
+    /// Opens or creates the database at `path`. Rows of an old database
+    /// without sessions are assigned to `default_session`.
+    pub fn open<P: AsRef<Path>>(path: P, default_session: &Session) -> Result<SqliteStore> {
+        let mut conn = Connection::open(path)?;
+        SqliteStore::migrate(&mut conn, default_session)?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    fn migrate(conn: &mut Connection, default_session: &Session) -> Result<()> {
+        let version: i32 = conn.query_row("PRAGMA user_version", &[], |row| row.get(0))?;
+        if version == SCHEMA_VERSION {
+            return Ok(());
+        }
+
+        // Rebuilding a table another one refers to fails under foreign keys,
+        // which the bundled SQLite turns on. Switching them off is a no-op
+        // inside a transaction, so it is done around it.
+        let foreign_keys: i32 = conn.query_row("PRAGMA foreign_keys", &[], |row| row.get(0))?;
+        conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+        let migrated = SqliteStore::migrate_from(conn, version, default_session);
+        conn.execute_batch(&format!("PRAGMA foreign_keys = {}", foreign_keys))?;
+        migrated
+    }
+
+    fn migrate_from(conn: &mut Connection, version: i32, default_session: &Session) -> Result<()> {
+        let tables: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'day_commits'",
+            &[],
+            |row| row.get(0),
+        )?;
+
+        let tx = conn.transaction()?;
+        if tables == 0 {
+            tx.execute_batch(SCHEMA)?;
+        } else {
+            if version < 2 {
+                // execute_batch can't bind parameters
+                let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+                tx.execute_batch(&MIGRATE_V1
+                    .replace(":team_id", &quote(&default_session.team_id))
+                    .replace(":channel_id", &quote(&default_session.channel_id)))?;
+            }
+            if version < 3 {
+                tx.execute_batch(MIGRATE_V2)?;
+            }
+            if version < 4 {
+                tx.execute_batch(MIGRATE_V3)?;
+            }
+            if version < 5 {
+                tx.execute_batch(MIGRATE_V4)?;
+            }
+            if version < 6 {
+                tx.execute_batch(MIGRATE_V5)?;
+            }
+            if version < 7 {
+                tx.execute_batch(MIGRATE_V6)?;
+            }
+            if version < 8 {
+                tx.execute_batch(MIGRATE_V7)?;
+            }
+        }
+        tx.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn conn(&self) -> Result<MutexGuard<Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("SqliteStore").into())
+    }
+
+    /// Loads the whole tree of a `FsJsonStore` (working shift, current month
+    /// and archived months). Refuses to import into a non-empty database.
+    pub fn import(&self, source: &FsJsonStore) -> Result<usize> {
+        let mut conn = self.conn()?;
+        let existing: i64 =
+            conn.query_row("SELECT COUNT(*) FROM day_commits", &[], |row| row.get(0))?;
+        if existing > 0 {
+            bail!(ErrorKind::AlreadyInitialized);
+        }
+
+        let tx = conn.transaction()?;
+        let mut count = 0;
+
+        for session in source.get_sessions()? {
+            for ((year, month), days) in source.get_archives(&session)? {
+                let archive_id = Some(SqliteStore::archive_id(&tx, &session, year, month)?);
+                for day_commit in &days {
+                    SqliteStore::insert(&tx, &session, day_commit, COMMITTED, None, archive_id)?;
+                    count += 1;
+                }
+            }
+
+            match source.get_days(&session) {
+                Ok(days) => for day_commit in &days {
+                    SqliteStore::insert(&tx, &session, day_commit, COMMITTED, None, None)?;
+                    count += 1;
+                },
+                Err(::Error(ErrorKind::NotInitialized, _)) => {}
+                Err(e) => return Err(e),
+            }
+
+            for (branch, day_commit) in source.get_branches(&session)? {
+                let branch = branch.as_ref().map(|b| b.as_str());
+                SqliteStore::insert(&tx, &session, &day_commit, WORKING, branch, None)?;
+                count += 1;
+            }
+
+            // Oldest first, so the latest gets the highest id
+            for day_commit in source.get_stashes(&session)?.iter().rev() {
+                let branch = day_commit.stashed_branch.as_ref().map(|b| b.as_str());
+                SqliteStore::insert(&tx, &session, day_commit, STASHED, branch, None)?;
+                count += 1;
+            }
+        }
+
+        tx.commit()?;
+        Ok(count)
+    }
+
+    fn archive_id(tx: &Transaction, session: &Session, year: i32, month: u32) -> Result<i64> {
+        tx.execute(
+            "INSERT OR IGNORE INTO archives (team_id, channel_id, year, month) \
+             VALUES (?, ?, ?, ?)",
+            &[&session.team_id, &session.channel_id, &year, &month],
+        )?;
+        tx.query_row(
+            "SELECT id FROM archives \
+             WHERE team_id = ? AND channel_id = ? AND year = ? AND month = ?",
+            &[&session.team_id, &session.channel_id, &year, &month],
+            |row| row.get(0),
+        ).map_err(|e| e.into())
+    }
+
+    fn insert(
+        tx: &Transaction,
+        session: &Session,
+        day_commit: &DayCommit,
+        working: i32,
+        branch: Option<&str>,
+        archive_id: Option<i64>,
+    ) -> Result<()> {
+        let end_hour = day_commit.end_time.as_ref().map(|t| t.0);
+        let end_minute = day_commit.end_time.as_ref().map(|t| t.1);
+        let (end_year, end_month, end_day) = date_columns(&day_commit.end_date);
+        let stashed_date = day_commit.stashed_at.as_ref().map(|s| s.0.clone());
+        let (stashed_year, stashed_month, stashed_day) = date_columns(&stashed_date);
+        let stashed_hour = day_commit.stashed_at.as_ref().map(|s| (s.1).0);
+        let stashed_minute = day_commit.stashed_at.as_ref().map(|s| (s.1).1);
+        tx.execute(
+            "INSERT INTO day_commits (team_id, channel_id, year, month, day, \
+             start_hour, start_minute, end_hour, end_minute, end_year, end_month, end_day, \
+             message, timezone, working, branch, archive_id, \
+             stashed_year, stashed_month, stashed_day, stashed_hour, stashed_minute) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            &[
+                &session.team_id,
+                &session.channel_id,
+                &day_commit.date.0,
+                &day_commit.date.1,
+                &day_commit.date.2,
+                &day_commit.start_time.0,
+                &day_commit.start_time.1,
+                &end_hour,
+                &end_minute,
+                &end_year,
+                &end_month,
+                &end_day,
+                &day_commit.message,
+                &day_commit.timezone,
+                &working,
+                &branch,
+                &archive_id,
+                &stashed_year,
+                &stashed_month,
+                &stashed_day,
+                &stashed_hour,
+                &stashed_minute,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        SqliteStore::insert_breaks(tx, id, None, &day_commit.breaks)?;
+        SqliteStore::insert_participants(tx, id, &day_commit.participants)
+    }
+
+    fn insert_participants(tx: &Transaction, id: i64, participants: &[Participant]) -> Result<()> {
+        for p in participants {
+            tx.execute(
+                "INSERT INTO participants (day_commit_id, name, commit_hour, commit_minute) \
+                 VALUES (?, ?, ?, ?)",
+                &[&id, &p.name, &p.commit_time.0, &p.commit_time.1],
+            )?;
+            let participant_id = tx.last_insert_rowid();
+            for i in p.stays() {
+                let out_hour = i.out_time.as_ref().map(|t| t.0);
+                let out_minute = i.out_time.as_ref().map(|t| t.1);
+                let (in_year, in_month, in_day) = date_columns(&i.in_date);
+                let (out_year, out_month, out_day) = date_columns(&i.out_date);
+                tx.execute(
+                    "INSERT INTO participant_intervals \
+                     (participant_id, in_hour, in_minute, out_hour, out_minute, \
+                     in_year, in_month, in_day, out_year, out_month, out_day) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    &[
+                        &participant_id,
+                        &i.in_time.0,
+                        &i.in_time.1,
+                        &out_hour,
+                        &out_minute,
+                        &in_year,
+                        &in_month,
+                        &in_day,
+                        &out_year,
+                        &out_month,
+                        &out_day,
+                    ],
+                )?;
+            }
+            SqliteStore::insert_breaks(tx, id, Some(participant_id), &p.breaks)?;
+        }
+        Ok(())
+    }
+
+    /// Breaks of the whole shift have no `participant_id`.
+    fn insert_breaks(
+        tx: &Transaction,
+        id: i64,
+        participant_id: Option<i64>,
+        breaks: &[Break],
+    ) -> Result<()> {
+        for b in breaks {
+            let end_hour = b.end_time.as_ref().map(|t| t.0);
+            let end_minute = b.end_time.as_ref().map(|t| t.1);
+            let (end_year, end_month, end_day) = date_columns(&b.end_date);
+            tx.execute(
+                "INSERT INTO breaks (day_commit_id, participant_id, \
+                 start_year, start_month, start_day, start_hour, start_minute, \
+                 end_year, end_month, end_day, end_hour, end_minute, reason) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                &[
+                    &id,
+                    &participant_id,
+                    &b.start_date.0,
+                    &b.start_date.1,
+                    &b.start_date.2,
+                    &b.start_time.0,
+                    &b.start_time.1,
+                    &end_year,
+                    &end_month,
+                    &end_day,
+                    &end_hour,
+                    &end_minute,
+                    &b.reason,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn delete_participants(tx: &Transaction, id: i64) -> Result<()> {
+        tx.execute("DELETE FROM breaks WHERE day_commit_id = ?", &[&id])?;
+        tx.execute(
+            "DELETE FROM participant_intervals WHERE participant_id IN \
+             (SELECT id FROM participants WHERE day_commit_id = ?)",
+            &[&id],
+        )?;
+        tx.execute("DELETE FROM participants WHERE day_commit_id = ?", &[&id])?;
+        Ok(())
+    }
+
+    fn row_to_commit(row: &Row) -> (i64, DayCommit) {
+        let end_hour: Option<u32> = row.get(6);
+        let end_minute: Option<u32> = row.get(7);
+        let stashed_hour: Option<u32> = row.get(16);
+        let stashed_minute: Option<u32> = row.get(17);
+        let working: i32 = row.get(18);
+        (
+            row.get(0),
+            DayCommit {
+                date: Date(row.get(1), row.get(2), row.get(3)),
+                start_time: Time(row.get(4), row.get(5)),
+                end_time: match (end_hour, end_minute) {
+                    (Some(h), Some(m)) => Some(Time(h, m)),
+                    _ => None,
+                },
+                end_date: row_date(row, 9),
+                message: row.get(8),
+                participants: vec![],
+                timezone: row.get(12),
+                breaks: vec![],
+                stashed_at: match (row_date(row, 13), stashed_hour, stashed_minute) {
+                    (Some(date), Some(h), Some(m)) => Some((date, Time(h, m))),
+                    _ => None,
+                },
+                stashed_branch: if working == STASHED {
+                    row.get(19)
+                } else {
+                    None
+                },
+            },
+        )
+    }
+
+    /// `params` are bound to the placeholders of `condition`.
+    fn select(
+        conn: &Connection,
+        session: &Session,
+        condition: &str,
+        params: &[&ToSql],
+    ) -> Result<Vec<DayCommit>> {
+        Ok(SqliteStore::select_with_id(conn, session, condition, params)?
+            .into_iter()
+            .map(|(_, day_commit)| day_commit)
+            .collect())
+    }
+
+    fn select_with_id(
+        conn: &Connection,
+        session: &Session,
+        condition: &str,
+        params: &[&ToSql],
+    ) -> Result<Vec<(i64, DayCommit)>> {
+        let sql = format!(
+            "SELECT {} FROM day_commits WHERE team_id = ? AND channel_id = ? AND {} \
+             ORDER BY year, month, day, id",
+            DAY_COMMIT_COLUMNS, condition
+        );
+        let mut all_params: Vec<&ToSql> = vec![&session.team_id, &session.channel_id];
+        all_params.extend_from_slice(params);
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(&all_params, SqliteStore::row_to_commit)?;
+
+        let mut commits = vec![];
+        for row in rows {
+            let (id, mut day_commit) = row?;
+            day_commit.breaks = SqliteStore::select_breaks(conn, id, None)?;
+            day_commit.participants = SqliteStore::select_participants(conn, id)?;
+            commits.push((id, day_commit));
+        }
+        Ok(commits)
+    }
+
+    fn select_participants(conn: &Connection, id: i64) -> Result<Vec<Participant>> {
+        let mut stmt = conn.prepare(
+            "SELECT id, name, commit_hour, commit_minute FROM participants \
+             WHERE day_commit_id = ? ORDER BY id",
+        )?;
+        let rows = stmt.query_map(&[&id], |row| {
+            let id: i64 = row.get(0);
+            (
+                id,
+                Participant {
+                    name: row.get(1),
+                    commit_time: Time(row.get(2), row.get(3)),
+                    intervals: vec![],
+                    breaks: vec![],
+                },
+            )
+        })?;
+
+        let mut participants = vec![];
+        for row in rows {
+            let (participant_id, mut p) = row?;
+            p.intervals = SqliteStore::select_intervals(conn, participant_id)?;
+            p.breaks = SqliteStore::select_breaks(conn, id, Some(participant_id))?;
+            participants.push(p);
+        }
+        Ok(participants)
+    }
+
+    fn select_intervals(conn: &Connection, participant_id: i64) -> Result<Vec<Interval>> {
+        let mut stmt = conn.prepare(
+            "SELECT in_hour, in_minute, out_hour, out_minute, \
+             in_year, in_month, in_day, out_year, out_month, out_day \
+             FROM participant_intervals WHERE participant_id = ? ORDER BY id",
+        )?;
+        let rows = stmt.query_map(&[&participant_id], |row| {
+            let out_hour: Option<u32> = row.get(2);
+            let out_minute: Option<u32> = row.get(3);
+            Interval {
+                in_time: Time(row.get(0), row.get(1)),
+                out_time: match (out_hour, out_minute) {
+                    (Some(h), Some(m)) => Some(Time(h, m)),
+                    _ => None,
+                },
+                in_date: row_date(row, 4),
+                out_date: row_date(row, 7),
+            }
+        })?;
+
+        let mut intervals = vec![];
+        for i in rows {
+            intervals.push(i?);
+        }
+        Ok(intervals)
+    }
+
+    fn select_breaks(
+        conn: &Connection,
+        id: i64,
+        participant_id: Option<i64>,
+    ) -> Result<Vec<Break>> {
+        let mut stmt = conn.prepare(
+            "SELECT start_year, start_month, start_day, start_hour, start_minute, \
+             end_year, end_month, end_day, end_hour, end_minute, reason \
+             FROM breaks WHERE day_commit_id = ? AND participant_id IS ? ORDER BY id",
+        )?;
+        let rows = stmt.query_map(&[&id, &participant_id], |row| {
+            let end_hour: Option<u32> = row.get(8);
+            let end_minute: Option<u32> = row.get(9);
+            Break {
+                start_date: Date(row.get(0), row.get(1), row.get(2)),
+                start_time: Time(row.get(3), row.get(4)),
+                end_date: row_date(row, 5),
+                end_time: match (end_hour, end_minute) {
+                    (Some(h), Some(m)) => Some(Time(h, m)),
+                    _ => None,
+                },
+                reason: row.get(10),
+            }
+        })?;
+
+        let mut breaks = vec![];
+        for b in rows {
+            breaks.push(b?);
+        }
+        Ok(breaks)
+    }
+
+    fn working_id(
+        conn: &Connection,
+        session: &Session,
+        branch: Option<&str>,
+    ) -> Result<Option<i64>> {
+        let mut stmt = conn.prepare(
+            "SELECT id FROM day_commits \
+             WHERE team_id = ? AND channel_id = ? AND working = ? AND branch IS ?",
+        )?;
+        let mut rows = stmt.query_map(
+            &[&session.team_id, &session.channel_id, &WORKING, &branch],
+            |row| row.get(0),
+        )?;
+        match rows.next() {
+            Some(id) => Ok(Some(id?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Row id of a committed day of the current month.
+    fn day_id(conn: &Connection, session: &Session, key: &DayKey) -> Result<i64> {
+        let days = SqliteStore::select_with_id(
+            conn,
+            session,
+            "working = ? AND archive_id IS NULL AND day = ?",
+            &[&COMMITTED, &key.day],
+        )?;
+        match days.get(key.n) {
+            Some(&(id, _)) => Ok(id),
+            None => bail!(ErrorKind::NoSuchDay(key.to_string())),
+        }
+    }
+
+    /// Stashed shifts with their row ids, the latest first.
+    fn stashes(conn: &Connection, session: &Session) -> Result<Vec<(i64, DayCommit)>> {
+        let mut stashes = SqliteStore::select_with_id(conn, session, "working = ?", &[&STASHED])?;
+        stashes.sort_by_key(|&(id, _)| ::std::cmp::Reverse(id));
+        Ok(stashes)
+    }
+
+    fn delete(tx: &Transaction, id: i64) -> Result<()> {
+        SqliteStore::delete_participants(tx, id)?;
+        tx.execute("DELETE FROM day_commits WHERE id = ?", &[&id])?;
+        Ok(())
+    }
+}
+
+fn date_columns(date: &Option<Date>) -> (Option<i32>, Option<u32>, Option<u32>) {
+    match *date {
+        Some(Date(year, month, day)) => (Some(year), Some(month), Some(day)),
+        None => (None, None, None),
+    }
+}
+
+/// Year, month and day at `i`, `i + 1` and `i + 2`.
+fn row_date(row: &Row, i: usize) -> Option<Date> {
+    let year: Option<i32> = row.get(i);
+    let month: Option<u32> = row.get(i + 1);
+    let day: Option<u32> = row.get(i + 2);
+    match (year, month, day) {
+        (Some(year), Some(month), Some(day)) => Some(Date(year, month, day)),
+        _ => None,
+    }
+}
+
+impl Store for SqliteStore {
+    fn lock(&self, _session: &Session) -> Result<StoreLock> {
+        let guard = self.write_lock
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("SqliteStore write lock"))?;
+        Ok(Box::new(guard))
+    }
+
+    fn create_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let mut conn = self.conn()?;
+        if SqliteStore::working_id(&conn, session, branch)?.is_some() {
+            bail!(ErrorKind::AlreadyInitialized);
+        }
+        let tx = conn.transaction()?;
+        SqliteStore::insert(&tx, session, day_commit, WORKING, branch, None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_working(&self, session: &Session, branch: Option<&str>) -> Result<DayCommit> {
+        let conn = self.conn()?;
+        match SqliteStore::select(
+            &conn,
+            session,
+            "working = ? AND branch IS ?",
+            &[&WORKING, &branch],
+        )?.pop()
+        {
+            Some(day_commit) => Ok(day_commit),
+            None => bail!(ErrorKind::NotInitialized),
+        }
+    }
+
+    fn get_branches(&self, session: &Session) -> Result<Vec<(Option<String>, DayCommit)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT branch FROM day_commits WHERE team_id = ? AND channel_id = ? AND working = ? \
+             ORDER BY branch",
+        )?;
+        let rows = stmt.query_map(
+            &[&session.team_id, &session.channel_id, &WORKING],
+            |row| row.get(0),
+        )?;
+
+        let mut branches = vec![];
+        for branch in rows {
+            let branch: Option<String> = branch?;
+            let day_commit = SqliteStore::select(
+                &conn,
+                session,
+                "working = ? AND branch IS ?",
+                &[&WORKING, &branch],
+            )?.pop();
+            if let Some(day_commit) = day_commit {
+                branches.push((branch, day_commit));
+            }
+        }
+        Ok(branches)
+    }
+
+    fn put_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let mut conn = self.conn()?;
+        let id = match SqliteStore::working_id(&conn, session, branch)? {
+            Some(id) => id,
+            None => bail!(ErrorKind::NotInitialized),
+        };
+        let tx = conn.transaction()?;
+        SqliteStore::delete(&tx, id)?;
+        SqliteStore::insert(&tx, session, day_commit, WORKING, branch, None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove_working(&self, session: &Session, branch: Option<&str>) -> Result<()> {
+        let mut conn = self.conn()?;
+        let id = match SqliteStore::working_id(&conn, session, branch)? {
+            Some(id) => id,
+            None => bail!(ErrorKind::NotInitialized),
+        };
+        let tx = conn.transaction()?;
+        SqliteStore::delete(&tx, id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn stash_working(
+        &self,
+        session: &Session,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let mut conn = self.conn()?;
+        let id = match SqliteStore::working_id(&conn, session, branch)? {
+            Some(id) => id,
+            None => bail!(ErrorKind::NotInitialized),
+        };
+        let tx = conn.transaction()?;
+        SqliteStore::delete(&tx, id)?;
+        SqliteStore::insert(&tx, session, day_commit, STASHED, branch, None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_stashes(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        let conn = self.conn()?;
+        Ok(SqliteStore::stashes(&conn, session)?
+            .into_iter()
+            .map(|(_, day_commit)| day_commit)
+            .collect())
+    }
+
+    fn pop_stash(
+        &self,
+        session: &Session,
+        n: usize,
+        branch: Option<&str>,
+        day_commit: &DayCommit,
+    ) -> Result<()> {
+        let mut conn = self.conn()?;
+        let id = match SqliteStore::stashes(&conn, session)?.into_iter().nth(n) {
+            Some((id, _)) => id,
+            None => bail!(ErrorKind::NoSuchStash(n)),
+        };
+        if SqliteStore::working_id(&conn, session, branch)?.is_some() {
+            bail!(ErrorKind::AlreadyInitialized);
+        }
+        let tx = conn.transaction()?;
+        SqliteStore::delete(&tx, id)?;
+        SqliteStore::insert(&tx, session, day_commit, WORKING, branch, None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn commit_day(&self, session: &Session, day_commit: &DayCommit) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        SqliteStore::insert(&tx, session, day_commit, COMMITTED, None, None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_days(&self, session: &Session) -> Result<Vec<DayCommit>> {
+        let conn = self.conn()?;
+        let committed: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM day_commits \
+             WHERE team_id = ? AND channel_id = ? AND working = ?",
+            &[&session.team_id, &session.channel_id, &COMMITTED],
+            |row| row.get(0),
+        )?;
+        if committed == 0 {
+            bail!(ErrorKind::NotInitialized);
+        }
+        SqliteStore::select(
+            &conn,
+            session,
+            "working = ? AND archive_id IS NULL",
+            &[&COMMITTED],
+        )
+    }
+
+    fn get_keyed_days(&self, session: &Session) -> Result<Vec<(DayKey, DayCommit)>> {
+        self.get_days(session).map(key_days)
+    }
+
+    fn put_day(&self, session: &Session, key: &DayKey, day_commit: &DayCommit) -> Result<()> {
+        let mut conn = self.conn()?;
+        let id = SqliteStore::day_id(&conn, session, key)?;
+
+        let end_hour = day_commit.end_time.as_ref().map(|t| t.0);
+        let end_minute = day_commit.end_time.as_ref().map(|t| t.1);
+        let (end_year, end_month, end_day) = date_columns(&day_commit.end_date);
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE day_commits SET year = ?, month = ?, day = ?, start_hour = ?, \
+             start_minute = ?, end_hour = ?, end_minute = ?, end_year = ?, end_month = ?, \
+             end_day = ?, message = ?, timezone = ? WHERE id = ?",
+            &[
+                &day_commit.date.0,
+                &day_commit.date.1,
+                &day_commit.date.2,
+                &day_commit.start_time.0,
+                &day_commit.start_time.1,
+                &end_hour,
+                &end_minute,
+                &end_year,
+                &end_month,
+                &end_day,
+                &day_commit.message,
+                &day_commit.timezone,
+                &id,
+            ],
+        )?;
+        SqliteStore::delete_participants(&tx, id)?;
+        SqliteStore::insert_breaks(&tx, id, None, &day_commit.breaks)?;
+        SqliteStore::insert_participants(&tx, id, &day_commit.participants)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove_day(&self, session: &Session, key: &DayKey) -> Result<()> {
+        let mut conn = self.conn()?;
+        let id = SqliteStore::day_id(&conn, session, key)?;
+        let tx = conn.transaction()?;
+        SqliteStore::delete(&tx, id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn push_month(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let mut conn = self.conn()?;
+        let days = SqliteStore::select(
+            &conn,
+            session,
+            "working = ? AND archive_id IS NULL",
+            &[&COMMITTED],
+        )?;
+        if days.is_empty() {
+            bail!(ErrorKind::NotInitialized);
+        }
+        let months: Vec<(i32, u32)> = group_by_month(days)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+
+        let tx = conn.transaction()?;
+        for &(year, month) in &months {
+            let archive_id = SqliteStore::archive_id(&tx, session, year, month)?;
+            tx.execute(
+                "UPDATE day_commits SET archive_id = ? \
+                 WHERE team_id = ? AND channel_id = ? AND working = ? AND archive_id IS NULL \
+                 AND year = ? AND month = ?",
+                &[
+                    &archive_id,
+                    &session.team_id,
+                    &session.channel_id,
+                    &COMMITTED,
+                    &year,
+                    &month,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(months)
+    }
+
+    fn get_month(&self, session: &Session, year: i32, month: u32) -> Result<Vec<DayCommit>> {
+        let conn = self.conn()?;
+        SqliteStore::select(
+            &conn,
+            session,
+            "archive_id = (SELECT id FROM archives \
+             WHERE team_id = ? AND channel_id = ? AND year = ? AND month = ?)",
+            &[&session.team_id, &session.channel_id, &year, &month],
+        )
+    }
+
+    fn get_months(&self, session: &Session) -> Result<Vec<(i32, u32)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT year, month FROM archives WHERE team_id = ? AND channel_id = ? \
+             ORDER BY year, month",
+        )?;
+        let rows = stmt.query_map(&[&session.team_id, &session.channel_id], |row| {
+            (row.get(0), row.get(1))
+        })?;
+
+        let mut months = vec![];
+        for row in rows {
+            months.push(row?);
+        }
+        Ok(months)
+    }
+
+    fn unpush_month(&self, session: &Session, year: i32, month: u32) -> Result<()> {
+        let mut conn = self.conn()?;
+        let archive_id: Option<i64> = {
+            let mut stmt = conn.prepare(
+                "SELECT id FROM archives \
+                 WHERE team_id = ? AND channel_id = ? AND year = ? AND month = ?",
+            )?;
+            let mut rows = stmt.query_map(
+                &[&session.team_id, &session.channel_id, &year, &month],
+                |row| row.get(0),
+            )?;
+            match rows.next() {
+                Some(id) => Some(id?),
+                None => None,
+            }
+        };
+        let archive_id = match archive_id {
+            Some(id) => id,
+            None => bail!(ErrorKind::NotArchived(year, month)),
+        };
+
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE day_commits SET archive_id = NULL WHERE archive_id = ?",
+            &[&archive_id],
+        )?;
+        tx.execute("DELETE FROM archives WHERE id = ?", &[&archive_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{self, Value};
+    use std::env;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    /// The schema before `user_version` was set, with no sessions
+    const SCHEMA_V0: &str = "
+    CREATE TABLE archives (
+        id INTEGER PRIMARY KEY,
+        year INTEGER NOT NULL,
+        month INTEGER NOT NULL,
+        UNIQUE (year, month)
+    );
+    CREATE TABLE day_commits (
+        id INTEGER PRIMARY KEY,
+        year INTEGER NOT NULL,
+        month INTEGER NOT NULL,
+        day INTEGER NOT NULL,
+        start_hour INTEGER NOT NULL,
+        start_minute INTEGER NOT NULL,
+        end_hour INTEGER,
+        end_minute INTEGER,
+        message TEXT,
+        working INTEGER NOT NULL DEFAULT 0,
+        archive_id INTEGER REFERENCES archives (id)
+    );
+    CREATE TABLE participants (
+        id INTEGER PRIMARY KEY,
+        day_commit_id INTEGER NOT NULL REFERENCES day_commits (id),
+        name TEXT NOT NULL,
+        commit_hour INTEGER NOT NULL,
+        commit_minute INTEGER NOT NULL
+    );
+    CREATE INDEX participants_name ON participants (name);
+    INSERT INTO archives (id, year, month) VALUES (1, 2018, 2);
+    INSERT INTO day_commits
+        (id, year, month, day, start_hour, start_minute, end_hour, end_minute, message, working,
+         archive_id)
+        VALUES (1, 2018, 2, 27, 9, 0, 18, 0, 'archived', 0, 1),
+               (2, 2018, 3, 2, 9, 0, 18, 30, 'committed', 0, NULL),
+               (3, 2018, 3, 5, 10, 0, NULL, NULL, NULL, 1, NULL);
+    INSERT INTO participants (day_commit_id, name, commit_hour, commit_minute)
+        VALUES (2, 'a', 9, 30), (3, 'b', 10, 0);
+    ";
+
+    fn session(channel_id: &str) -> Session {
+        Session {
+            team_id: "T1".to_owned(),
+            channel_id: channel_id.to_owned(),
+        }
+    }
+
+    fn day(date: (i32, u32, u32), message: &str) -> DayCommit {
+        serde_json::from_str(&format!(
+            r#"{{"date": [{}, {}, {}], "start_time": [9, 0], "end_time": [18, 0],
+                "message": "{}", "participants": []}}"#,
+            date.0, date.1, date.2, message
+        )).unwrap()
+    }
+
+    fn json<T: ::serde::Serialize>(value: T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn migrate_v0() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_V0).unwrap();
+        SqliteStore::migrate(&mut conn, &session("C1")).unwrap();
+        let version: i32 = conn.query_row("PRAGMA user_version", &[], |row| row.get(0))
+            .unwrap();
+        let foreign_keys: i32 = conn.query_row("PRAGMA foreign_keys", &[], |row| row.get(0))
+            .unwrap();
+        assert_eq!(foreign_keys, 1);
+        assert_eq!(version, SCHEMA_VERSION);
+        let store = SqliteStore {
+            conn: Mutex::new(conn),
+            write_lock: Mutex::new(()),
+        };
+
+        let other = session("C2");
+        let session = session("C1");
+        assert_eq!(store.get_months(&session).unwrap(), [(2018, 2)]);
+        let archived = store.get_month(&session, 2018, 2).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].message, Some("archived".to_owned()));
+        assert_eq!(archived[0].end_time, Some(Time(18, 0)));
+
+        let days = store.get_days(&session).unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].message, Some("committed".to_owned()));
+        assert_eq!(days[0].end(), Some(Date(2018, 3, 2).at(&Time(18, 30))));
+        let stays = days[0].participants[0].stays();
+        assert_eq!(stays.len(), 1);
+        assert_eq!(stays[0].in_time, Time(9, 30));
+        assert_eq!(stays[0].out_time, None);
+
+        let working = store.get_working(&session, None).unwrap();
+        assert_eq!(working.start_time, Time(10, 0));
+        assert_eq!(working.participants[0].name, "b");
+        assert!(working.timezone.is_none() && working.breaks.is_empty());
+
+        assert!(store.get_months(&other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn import_fs_json() {
+        let path = env::temp_dir().join("glt-sqlite-import");
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        let source = FsJsonStore::new(&path);
+        let session = session("C1");
+
+        source.commit_day(&session, &day((2018, 2, 27), "archived")).unwrap();
+        source.push_month(&session).unwrap();
+        source.commit_day(&session, &day((2018, 3, 2), "committed")).unwrap();
+        source.create_working(&session, None, &day((2018, 3, 5), "working")).unwrap();
+        let evening = Some("evening");
+        source.create_working(&session, evening, &day((2018, 3, 5), "evening")).unwrap();
+        for &(branch, message) in &[("night", "stashed first"), ("late", "stashed last")] {
+            let mut stashed = day((2018, 3, 4), message);
+            source.create_working(&session, Some(branch), &stashed).unwrap();
+            stashed.stashed_branch = Some(branch.to_owned());
+            source.stash_working(&session, Some(branch), &stashed).unwrap();
+        }
+
+        let store = SqliteStore::open(":memory:", &session).unwrap();
+        assert_eq!(store.import(&source).unwrap(), 6);
+        assert_eq!(store.get_months(&session).unwrap(), [(2018, 2)]);
+        assert_eq!(
+            json(store.get_month(&session, 2018, 2).unwrap()),
+            json(source.get_month(&session, 2018, 2).unwrap())
+        );
+        let (keys, days): (Vec<_>, Vec<_>) =
+            store.get_keyed_days(&session).unwrap().into_iter().unzip();
+        let (source_keys, source_days): (Vec<_>, Vec<_>) =
+            source.get_keyed_days(&session).unwrap().into_iter().unzip();
+        assert_eq!((keys, json(days)), (source_keys, json(source_days)));
+        assert_eq!(
+            json(store.get_branches(&session).unwrap()),
+            json(source.get_branches(&session).unwrap())
+        );
+        let stashes = store.get_stashes(&session).unwrap();
+        assert_eq!(json(&stashes), json(source.get_stashes(&session).unwrap()));
+        assert_eq!(stashes[0].message, Some("stashed last".to_owned()));
+        assert_eq!(stashes[0].stashed_branch, Some("late".to_owned()));
+
+        match store.import(&source) {
+            Err(::Error(ErrorKind::AlreadyInitialized, _)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use super::{ErrorKind, Result};
+use app::App;
+use reqwest::Client;
+use serde_json::Value;
+use slack::slash_command::Request;
+use std::ops::Deref;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Runs slow commands off the request thread, one at a time, and posts what
+/// they answer, or the responses to button clicks, to their `response_url`.
+/// A post that fails is retried on a thread of its own, so a `response_url`
+/// that is down doesn't hold up the jobs after it.
+pub struct Worker {
+    jobs: Mutex<Sender<Job>>,
+}
+
+pub enum Job {
+    /// A delayed command, answered with what it is run into
+    Command(Request),
+    /// A response ready to be posted to a `response_url`
+    Post(String, Value),
+}
+
+impl Worker {
+    /// Starts the thread that answers commands with `run`, on an `app` that
+    /// lives as long as the thread, like a `&'static App` or an `Arc<App>`.
+    pub fn start<A>(app: A, run: fn(&App, Request) -> Result<Value>) -> Worker
+    where
+        A: Deref<Target = App> + Send + 'static,
+    {
+        let (sender, receiver) = channel::<Job>();
+        thread::spawn(move || {
+            let client = Client::new();
+            for job in receiver {
+                let (url, what, response) = match job {
+                    Job::Command(data) => (
+                        data.response_url.clone(),
+                        format!("`{}` in {}", data.text, data.channel_id),
+                        run(&app, data),
+                    ),
+                    Job::Post(url, response) => (url, "an action".to_owned(), Ok(response)),
+                };
+                let retries = app.settings.response_retries;
+                match response {
+                    Ok(response) => post_later(&client, url, response, retries, what),
+                    Err(e) => eprintln!("Failed to answer {}: {}", what, e),
+                }
+            }
+        });
+        Worker {
+            jobs: Mutex::new(sender),
+        }
+    }
+
+    pub fn push(&self, job: Job) -> Result<()> {
+        let jobs = self.jobs
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("worker jobs"))?;
+        jobs.send(job).map_err(|_| ErrorKind::WorkerStopped.into())
+    }
+}
+
+/// Posts `response` to `url` once, and if that fails, goes on trying on
+/// another thread as `post_response` does.
+fn post_later(client: &Client, url: String, response: Value, retries: u32, what: String) {
+    match post_once(client, &url, &response) {
+        Ok(()) => {}
+        Err(_) if retries > 0 => {
+            let client = client.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_secs(1));
+                if let Err(e) = post_response_after(&client, &url, &response, retries, 1) {
+                    eprintln!("Failed to answer {}: {}", what, e);
+                }
+            });
+        }
+        Err(e) => eprintln!("Failed to answer {}: {}", what, e),
+    }
+}
+
+fn post_once(client: &Client, url: &str, response: &Value) -> Result<()> {
+    match client.post(url).json(response).send() {
+        Ok(ref r) if r.status().is_success() => Ok(()),
+        Ok(r) => Err(ErrorKind::ResponseFailed(r.status().to_string()).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Posts `response` to `url`, trying `retries` more times, 1, 2, 4... up to 64
+/// seconds apart, while it can't be sent or isn't answered with a success.
+pub fn post_response(client: &Client, url: &str, response: &Value, retries: u32) -> Result<()> {
+    post_response_after(client, url, response, retries, 0)
+}
+
+/// `post_response` from its `attempt`th try on
+fn post_response_after(
+    client: &Client,
+    url: &str,
+    response: &Value,
+    retries: u32,
+    mut attempt: u32,
+) -> Result<()> {
+    loop {
+        let result = post_once(client, url, response);
+        if result.is_ok() || attempt >= retries {
+            return result;
+        }
+        thread::sleep(Duration::from_secs(1u64 << attempt.min(6)));
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+    use std::time::Instant;
+    use store::memory::MemoryStore;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers requests with `statuses` in turn, the last one over and over,
+    /// and counts the requests that had a JSON body.
+    fn serve(statuses: &'static [u16]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/response", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let lower = line.to_lowercase();
+                    if lower.starts_with("content-length:") {
+                        length = lower["content-length:".len()..].trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let n = counted.load(Ordering::SeqCst);
+                if body == br#"{"text":"hi"}"# {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                }
+                let status = statuses[n.min(statuses.len() - 1)];
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                ).unwrap();
+            }
+        });
+        (url, count)
+    }
+
+    fn response() -> Value {
+        ::serde_json::from_str(r#"{"text":"hi"}"#).unwrap()
+    }
+
+    #[test]
+    fn post_response_success() {
+        let (url, count) = serve(&[200]);
+        post_response(&Client::new(), &url, &response(), 3).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn post_response_retries_server_error() {
+        let (url, count) = serve(&[503, 200]);
+        post_response(&Client::new(), &url, &response(), 1).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn post_response_gives_up() {
+        let (url, count) = serve(&[500]);
+        match post_response(&Client::new(), &url, &response(), 1) {
+            Err(Error(ErrorKind::ResponseFailed(ref status), _)) if status.starts_with("500") => {}
+            r => panic!("{:?}", r),
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    fn unused(_: &App, _: Request) -> Result<Value> {
+        unreachable!()
+    }
+
+    /// Waits up to `seconds` for `count` to reach `n`
+    fn wait_for(count: &AtomicUsize, n: usize, seconds: u64) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(seconds) {
+            if count.load(Ordering::SeqCst) >= n {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn retries_dont_hold_up_jobs() {
+        let settings = ::serde_json::from_str(
+            r#"{"api_token": "", "data_path": "/tmp/", "response_retries": 3}"#,
+        ).unwrap();
+        let app = Arc::new(App::with_store(settings, Box::new(MemoryStore::new())));
+        let worker = Worker::start(app, unused);
+        let (failing, failed) = serve(&[500]);
+        let (working, posted) = serve(&[200]);
+        worker.push(Job::Post(failing, response())).unwrap();
+        worker.push(Job::Post(working, response())).unwrap();
+        assert!(wait_for(&posted, 1, 1));
+        assert!(wait_for(&failed, 2, 3));
+    }
+}